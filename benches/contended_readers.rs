@@ -0,0 +1,72 @@
+//! Measures the read throughput of readers on different threads without a writer, whose epochs
+//! are allocated next to each other, run with `cargo bench --bench contended_readers`.
+//!
+//! The epochs aren't padded, so they may share cache lines, which this shows the cost of on
+//! machines with several cores.
+
+extern crate criterion;
+extern crate evc;
+
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+#[derive(Clone, Default)]
+struct Counter(u64);
+
+impl evc::OperationCache for Counter {
+    type Operation = u64;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        self.0 += operation;
+    }
+}
+
+/// Measure how long it takes for every reader to read `iterations` times.
+fn measure(readers: usize, iterations: u64) -> Duration {
+    let (_w_handle, r_handle) = evc::new(Counter::default());
+    let barrier = Arc::new(Barrier::new(readers + 1));
+
+    // The handles are created together, so that their epochs are allocated next to each other.
+    let r_handles = (0..readers).map(|_| r_handle.clone()).collect::<Vec<_>>();
+
+    let reader_threads = r_handles
+        .into_iter()
+        .map(|r_handle| {
+            let barrier = Arc::clone(&barrier);
+
+            thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..iterations {
+                    black_box(r_handle.read().0);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    barrier.wait();
+    let start = Instant::now();
+    for thread in reader_threads {
+        thread.join().unwrap();
+    }
+    start.elapsed()
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contended_readers");
+
+    for readers in [1, 2, 4, 8] {
+        group.throughput(Throughput::Elements(readers as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(readers),
+            &readers,
+            |b, &readers| b.iter_custom(|iterations| measure(readers, iterations)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
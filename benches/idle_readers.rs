@@ -0,0 +1,43 @@
+//! Measures refreshing with thousands of readers that have never read, along with one that reads
+//! between every refresh, run with `cargo bench --bench idle_readers`.
+
+extern crate criterion;
+extern crate evc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use evc::OperationCache;
+
+#[derive(Clone, Default)]
+struct Counter(u64);
+
+impl OperationCache for Counter {
+    type Operation = u64;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        self.0 += operation;
+    }
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("idle_readers");
+
+    for idle_readers in [0, 1_000, 10_000] {
+        let (mut w_handle, r_handle) = evc::new(Counter::default());
+        let _idle = (0..idle_readers)
+            .map(|_| r_handle.clone())
+            .collect::<Vec<_>>();
+
+        group.bench_function(BenchmarkId::from_parameter(idle_readers), |b| {
+            b.iter(|| {
+                w_handle.write(1);
+                w_handle.refresh();
+                drop(r_handle.read());
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+//! Compares cloning and dropping a plain read handle to a handle created by a factory, whose
+//! epoch is pooled, without refreshing in between, run with `cargo bench --bench pooled_handles`.
+
+extern crate criterion;
+extern crate evc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use evc::OperationCache;
+
+#[derive(Clone, Default)]
+struct Counter(u64);
+
+impl OperationCache for Counter {
+    type Operation = u64;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        self.0 += operation;
+    }
+}
+
+fn bench(c: &mut Criterion) {
+    let (_w_handle, r_handle) = evc::new(Counter::default());
+    let r_factory = r_handle.factory();
+    let pooled = r_factory.handle();
+
+    let mut group = c.benchmark_group("clone_handle");
+    group.bench_function("plain", |b| b.iter(|| drop(r_handle.clone())));
+    group.bench_function("pooled", |b| b.iter(|| drop(pooled.clone())));
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
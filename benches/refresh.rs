@@ -0,0 +1,48 @@
+//! Compares refreshing without readers with and without collapsing to a single buffer, run with
+//! `cargo bench --bench refresh`.
+
+extern crate criterion;
+extern crate evc;
+
+use std::collections::HashMap;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use evc::OperationCache;
+
+#[derive(Clone, Default)]
+struct Map(HashMap<u64, u64>);
+
+impl OperationCache for Map {
+    type Operation = (u64, u64);
+
+    fn apply_operation(&mut self, (key, value): Self::Operation) {
+        self.0.insert(key, value);
+    }
+}
+
+const OPERATIONS: u64 = 100;
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("collapse_without_readers");
+    group.throughput(Throughput::Elements(OPERATIONS));
+
+    for collapse in [false, true] {
+        let (mut w_handle, r_handle) = evc::new(Map::default());
+        w_handle.set_collapse_without_readers(collapse);
+        drop(r_handle);
+
+        let mut refresh = 0;
+        group.bench_function(BenchmarkId::from_parameter(collapse), |b| {
+            b.iter(|| {
+                w_handle.write_many((0..OPERATIONS).map(|key| (key, refresh)));
+                w_handle.refresh();
+                refresh += 1;
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
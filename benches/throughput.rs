@@ -0,0 +1,192 @@
+//! Compares the read throughput of `evc` to `RwLock` and `left-right` with a single writer
+//! constantly writing and publishing, run with `cargo bench --bench throughput`.
+
+extern crate criterion;
+extern crate evc;
+extern crate left_right;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const LEN: usize = 1024;
+
+/// Set the element at an index.
+#[derive(Clone, Copy)]
+struct Set(usize, u16);
+
+#[derive(Clone)]
+struct Values(Vec<u16>);
+
+impl Default for Values {
+    fn default() -> Self {
+        Values(vec![0; LEN])
+    }
+}
+
+impl evc::OperationCache for Values {
+    type Operation = Set;
+
+    fn apply_operation(&mut self, Set(index, value): Self::Operation) {
+        self.0[index] = value;
+    }
+}
+
+impl left_right::Absorb<Set> for Values {
+    fn absorb_first(&mut self, &mut Set(index, value): &mut Set, _: &Self) {
+        self.0[index] = value;
+    }
+    fn sync_with(&mut self, first: &Self) {
+        self.0.clone_from(&first.0);
+    }
+}
+
+/// A synchronization primitive with a single writer and any number of readers.
+trait Backend {
+    const NAME: &'static str;
+
+    type Writer: Send + 'static;
+    type Reader: Send + 'static;
+
+    fn new() -> (Self::Writer, Self::Reader);
+    fn clone_reader(reader: &Self::Reader) -> Self::Reader;
+    fn read(reader: &Self::Reader, index: usize) -> u16;
+    /// Write a value, and make it visible to the readers.
+    fn write(writer: &mut Self::Writer, index: usize, value: u16);
+}
+
+struct Evc;
+
+impl Backend for Evc {
+    const NAME: &'static str = "evc";
+
+    type Writer = evc::WriteHandle<Values>;
+    type Reader = evc::ReadHandle<Values>;
+
+    fn new() -> (Self::Writer, Self::Reader) {
+        evc::new(Values::default())
+    }
+    fn clone_reader(reader: &Self::Reader) -> Self::Reader {
+        reader.clone()
+    }
+    fn read(reader: &Self::Reader, index: usize) -> u16 {
+        reader.read().0[index]
+    }
+    fn write(writer: &mut Self::Writer, index: usize, value: u16) {
+        writer.write(Set(index, value));
+        writer.refresh();
+    }
+}
+
+struct StdRwLock;
+
+impl Backend for StdRwLock {
+    const NAME: &'static str = "rwlock";
+
+    type Writer = Arc<RwLock<Vec<u16>>>;
+    type Reader = Arc<RwLock<Vec<u16>>>;
+
+    fn new() -> (Self::Writer, Self::Reader) {
+        let lock = Arc::new(RwLock::new(vec![0; LEN]));
+        (Arc::clone(&lock), lock)
+    }
+    fn clone_reader(reader: &Self::Reader) -> Self::Reader {
+        Arc::clone(reader)
+    }
+    fn read(reader: &Self::Reader, index: usize) -> u16 {
+        reader.read().unwrap()[index]
+    }
+    fn write(writer: &mut Self::Writer, index: usize, value: u16) {
+        writer.write().unwrap()[index] = value;
+    }
+}
+
+struct LeftRight;
+
+impl Backend for LeftRight {
+    const NAME: &'static str = "left-right";
+
+    type Writer = left_right::WriteHandle<Values, Set>;
+    type Reader = left_right::ReadHandle<Values>;
+
+    fn new() -> (Self::Writer, Self::Reader) {
+        left_right::new::<Values, Set>()
+    }
+    fn clone_reader(reader: &Self::Reader) -> Self::Reader {
+        reader.clone()
+    }
+    fn read(reader: &Self::Reader, index: usize) -> u16 {
+        reader.enter().unwrap().0[index]
+    }
+    fn write(writer: &mut Self::Writer, index: usize, value: u16) {
+        writer.append(Set(index, value));
+        writer.publish();
+    }
+}
+
+/// Measure how long it takes for every reader to read `iterations` times, while the writer keeps
+/// writing.
+fn measure<B: Backend>(readers: usize, iterations: u64) -> Duration {
+    let (mut writer, reader) = B::new();
+    let barrier = Arc::new(Barrier::new(readers + 1));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let reader_threads = (0..readers)
+        .map(|_| {
+            let reader = B::clone_reader(&reader);
+            let barrier = Arc::clone(&barrier);
+
+            thread::spawn(move || {
+                barrier.wait();
+                for iteration in 0..iterations {
+                    black_box(B::read(&reader, iteration as usize % LEN));
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(reader);
+
+    let writer_thread = {
+        let stop = Arc::clone(&stop);
+
+        thread::spawn(move || {
+            let mut iteration = 0;
+            while !stop.load(Ordering::Relaxed) {
+                B::write(&mut writer, iteration % LEN, iteration as u16);
+                iteration += 1;
+            }
+        })
+    };
+
+    barrier.wait();
+    let start = Instant::now();
+    for thread in reader_threads {
+        thread.join().unwrap();
+    }
+    let elapsed = start.elapsed();
+
+    stop.store(true, Ordering::Relaxed);
+    writer_thread.join().unwrap();
+
+    elapsed
+}
+
+fn bench<B: Backend>(c: &mut Criterion) {
+    let mut group = c.benchmark_group("throughput");
+
+    for readers in [1, 4, 16, 64] {
+        group.throughput(Throughput::Elements(readers as u64));
+        group.bench_with_input(
+            BenchmarkId::new(B::NAME, readers),
+            &readers,
+            |b, &readers| b.iter_custom(|iterations| measure::<B>(readers, iterations)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench<Evc>, bench<StdRwLock>, bench<LeftRight>);
+criterion_main!(benches);
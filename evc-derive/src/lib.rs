@@ -0,0 +1,154 @@
+//! `#[derive(OperationCache)]` for `evc`, re-exported by `evc` behind its `derive` feature.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Ident, Member, Type, Variant};
+
+/// Implement `OperationCache` for a type, with the enum this is derived for as its operation.
+///
+/// The enum names the type with `#[evc(target = Type)]`, and every variant names the method of
+/// the type that applies it, e.g. `#[evc(push)]`, which is called with the fields of the variant
+/// in order. `#[evc(field = 0)]` on the enum calls the methods on a field of the type instead.
+///
+/// ```
+/// # extern crate evc;
+/// use evc::OperationCache;
+///
+/// #[derive(Clone, Debug, Default)]
+/// struct VecWrapper(Vec<u16>);
+///
+/// #[derive(Clone, Copy, Debug, OperationCache)]
+/// #[evc(target = VecWrapper, field = 0)]
+/// enum Operation {
+///     #[evc(push)]
+///     Push(u16),
+///     #[evc(remove)]
+///     Remove(usize),
+///     #[evc(clear)]
+///     Clear,
+/// }
+///
+/// let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+///
+/// w_handle.write_many([Operation::Push(42), Operation::Push(24), Operation::Remove(0)]);
+/// w_handle.refresh();
+/// assert_eq!(r_handle.read().0, &[24]);
+/// ```
+#[proc_macro_derive(OperationCache, attributes(evc))]
+pub fn derive_operation_cache(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let operation = &input.ident;
+    let mut target: Option<Type> = None;
+    let mut field: Option<Member> = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("evc"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("target") {
+                target = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("field") {
+                field = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `target` or `field`"))
+            }
+        })?;
+    }
+    let target = target.ok_or_else(|| {
+        Error::new_spanned(operation, "missing `#[evc(target = Type)]` on the enum")
+    })?;
+
+    let variants = match input.data {
+        Data::Enum(ref data) => &data.variants,
+        _ => {
+            return Err(Error::new_spanned(
+                operation,
+                "`OperationCache` can only be derived for enums of operations",
+            ))
+        }
+    };
+    let receiver = match field {
+        Some(field) => quote!(self.#field),
+        None => quote!(self),
+    };
+
+    let arms = variants
+        .iter()
+        .map(|variant| {
+            let method = variant_method(variant)?;
+            let name = &variant.ident;
+
+            let (pattern, args) = match variant.fields {
+                Fields::Unit => (quote!(#operation::#name), Vec::new()),
+                Fields::Unnamed(ref fields) => {
+                    let args = (0..fields.unnamed.len())
+                        .map(|index| format_ident!("field{}", index))
+                        .collect::<Vec<_>>();
+                    (quote!(#operation::#name(#(#args),*)), args)
+                }
+                Fields::Named(ref fields) => {
+                    let args = fields
+                        .named
+                        .iter()
+                        .map(|field| field.ident.clone().unwrap())
+                        .collect::<Vec<_>>();
+                    (quote!(#operation::#name { #(#args),* }), args)
+                }
+            };
+            // The methods may return e.g. the removed value, which isn't needed.
+            Ok(quote!(#pattern => {
+                let _ = #receiver.#method(#(#args),*);
+            }))
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::evc::OperationCache for #target #where_clause {
+            type Operation = #operation #ty_generics;
+
+            fn apply_operation(&mut self, operation: Self::Operation) {
+                match operation {
+                    #(#arms)*
+                }
+            }
+        }
+    })
+}
+
+/// Get the method named by the `#[evc(method)]` attribute of a variant.
+fn variant_method(variant: &Variant) -> syn::Result<Ident> {
+    let mut method = None;
+
+    for attr in variant
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("evc"))
+    {
+        attr.parse_nested_meta(|meta| match meta.path.get_ident() {
+            Some(ident) if method.is_none() => {
+                method = Some(ident.clone());
+                Ok(())
+            }
+            _ => Err(meta.error("expected a single method name")),
+        })?;
+    }
+    method.ok_or_else(|| {
+        Error::new_spanned(&variant.ident, "missing `#[evc(method)]` on the variant")
+    })
+}
@@ -0,0 +1,49 @@
+//! The strategy used while waiting for other threads, e.g. for readers to leave a buffer.
+
+use crate::sync;
+
+/// The number of steps that busy-wait, doubling the number of spins each step.
+const SPIN_LIMIT: u32 = 6;
+/// The step after which waiting starts to sleep, when there's a clock to sleep with.
+const YIELD_LIMIT: u32 = 10;
+/// The longest time to sleep for at once, in microseconds.
+#[cfg(all(feature = "std", not(loom)))]
+const MAX_SLEEP_MICROS: u64 = 1000;
+
+/// Exponential backoff, which spins at first, then yields to other threads, and finally sleeps
+/// for increasing intervals.
+#[derive(Debug, Default)]
+pub(crate) struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+    /// Wait a little longer than the previous call did.
+    pub(crate) fn snooze(&mut self) {
+        if self.step <= SPIN_LIMIT {
+            for _ in 0..1 << self.step {
+                sync::spin_loop();
+            }
+        } else if self.step <= YIELD_LIMIT {
+            sync::yield_now();
+        } else {
+            Self::park(self.step - YIELD_LIMIT);
+        }
+
+        self.step = self.step.saturating_add(1);
+    }
+    #[cfg(all(feature = "std", not(loom)))]
+    fn park(exponent: u32) {
+        let micros = 1u64.checked_shl(exponent).unwrap_or(u64::MAX);
+        std::thread::sleep(std::time::Duration::from_micros(
+            micros.min(MAX_SLEEP_MICROS),
+        ));
+    }
+    #[cfg(any(not(feature = "std"), loom))]
+    fn park(_exponent: u32) {
+        sync::yield_now();
+    }
+}
@@ -0,0 +1,92 @@
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::{OperationCache, ReadHandle, SyncStrategy, WriteHandle};
+
+/// Configures the writer of a new `evc`, for when the defaults of `evc::new` don't fit.
+///
+/// ```
+/// # use evc::OperationCache;
+/// # #[derive(Clone, Default)]
+/// # struct Counter(u64);
+/// # impl OperationCache for Counter {
+/// #     type Operation = u64;
+/// #     fn apply_operation(&mut self, operation: u64) {
+/// #         self.0 += operation;
+/// #     }
+/// # }
+/// let (mut w_handle, r_handle) = evc::Builder::new()
+///     .with_capacity(64)
+///     .auto_refresh(64)
+///     .build(Counter::default());
+///
+/// for _ in 0..64 {
+///     w_handle.write(1);
+/// }
+/// assert_eq!(r_handle.read().0, 64);
+/// ```
+pub struct Builder<T> {
+    ops_capacity: usize,
+    auto_refresh: Option<usize>,
+    sync_strategy: SyncStrategy,
+
+    _marker: PhantomData<fn(T) -> T>,
+}
+
+impl<T> Builder<T> {
+    /// Create a builder with the same configuration as `evc::new`.
+    pub fn new() -> Self {
+        Builder {
+            ops_capacity: 0,
+            auto_refresh: None,
+            sync_strategy: SyncStrategy::default(),
+
+            _marker: PhantomData,
+        }
+    }
+    /// Make room for `ops_capacity` operations to be queued before the operation buffer has to
+    /// reallocate, like `evc::new_with_capacity`.
+    pub fn with_capacity(mut self, ops_capacity: usize) -> Self {
+        self.ops_capacity = ops_capacity;
+        self
+    }
+    /// Refresh automatically whenever the number of pending operations reaches the threshold, see
+    /// `WriteHandle::set_auto_refresh`.
+    pub fn auto_refresh(mut self, threshold: usize) -> Self {
+        self.auto_refresh = Some(threshold);
+        self
+    }
+    /// Choose how the buffers are kept identical, see `WriteHandle::set_sync_strategy`.
+    pub fn sync_strategy(mut self, strategy: SyncStrategy) -> Self {
+        self.sync_strategy = strategy;
+        self
+    }
+}
+
+impl<T: Clone + OperationCache> Builder<T> {
+    /// Create a write handle and a read handle to some data, configured by this builder.
+    pub fn build(self, value: T) -> (WriteHandle<T>, ReadHandle<T>) {
+        let (mut w_handle, r_handle) = crate::new_with_capacity(value, self.ops_capacity);
+
+        w_handle.set_auto_refresh(self.auto_refresh);
+        w_handle.set_sync_strategy(self.sync_strategy);
+
+        (w_handle, r_handle)
+    }
+}
+
+impl<T> Default for Builder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Builder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("ops_capacity", &self.ops_capacity)
+            .field("auto_refresh", &self.auto_refresh)
+            .field("sync_strategy", &self.sync_strategy)
+            .finish()
+    }
+}
@@ -0,0 +1,233 @@
+//! Ready-made `OperationCache` wrappers around common containers, with the operations to mutate
+//! them. The wrappers dereference to the underlying container, so read guards can be used like the
+//! container itself, and `OperationCacheExt` provides the writing side.
+
+use alloc::vec::Vec;
+use core::ops::Deref;
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "std")]
+use std::hash::Hash;
+
+use crate::{OperationCache, WriteHandle};
+
+/// Writing to a container through a write handle, by queueing its operations.
+pub trait OperationCacheExt {
+    /// The item added to the container.
+    type Item;
+    /// What identifies the item to remove from the container.
+    type Key;
+
+    /// Queue adding an item to the container.
+    fn insert(&mut self, item: Self::Item);
+    /// Queue removing an item from the container.
+    fn remove(&mut self, key: Self::Key);
+    /// Queue removing all items from the container.
+    fn clear(&mut self);
+}
+
+/// A `Vec` that can be stored in an `evc`. Inserting through `OperationCacheExt` pushes to the end
+/// of the vector, and removing takes an index.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VecCache<T>(pub Vec<T>);
+
+/// An operation on a `VecCache`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VecOperation<T> {
+    /// Push a value to the end of the vector.
+    Push(T),
+    /// Remove the value at an index, shifting the values after it. Panics if the index is out of
+    /// bounds, like `Vec::remove`.
+    Remove(usize),
+    /// Remove all values.
+    Clear,
+}
+
+impl<T: Clone> OperationCache for VecCache<T> {
+    type Operation = VecOperation<T>;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        match operation {
+            VecOperation::Push(value) => self.0.push(value),
+            VecOperation::Remove(index) => {
+                self.0.remove(index);
+            }
+            VecOperation::Clear => self.0.clear(),
+        }
+    }
+}
+
+impl<T> Deref for VecCache<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T> From<Vec<T>> for VecCache<T> {
+    fn from(vec: Vec<T>) -> Self {
+        VecCache(vec)
+    }
+}
+
+impl<T: Clone> OperationCacheExt for WriteHandle<VecCache<T>> {
+    type Item = T;
+    type Key = usize;
+
+    fn insert(&mut self, value: T) {
+        self.write(VecOperation::Push(value))
+    }
+    fn remove(&mut self, index: usize) {
+        self.write(VecOperation::Remove(index))
+    }
+    fn clear(&mut self) {
+        self.write(VecOperation::Clear)
+    }
+}
+
+/// A `HashMap` that can be stored in an `evc`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HashMapCache<K: Eq + Hash, V>(pub HashMap<K, V>);
+
+/// An operation on a `HashMapCache`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HashMapOperation<K, V> {
+    /// Insert a value, replacing the previous value of the key.
+    Insert(K, V),
+    /// Remove the value of a key.
+    Remove(K),
+    /// Remove all keys.
+    Clear,
+}
+
+#[cfg(feature = "std")]
+impl<K: Clone + Eq + Hash, V: Clone> OperationCache for HashMapCache<K, V> {
+    type Operation = HashMapOperation<K, V>;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        match operation {
+            HashMapOperation::Insert(key, value) => {
+                self.0.insert(key, value);
+            }
+            HashMapOperation::Remove(key) => {
+                self.0.remove(&key);
+            }
+            HashMapOperation::Clear => self.0.clear(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Eq + Hash, V> Default for HashMapCache<K, V> {
+    fn default() -> Self {
+        HashMapCache(HashMap::new())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Eq + Hash, V> Deref for HashMapCache<K, V> {
+    type Target = HashMap<K, V>;
+
+    fn deref(&self) -> &HashMap<K, V> {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Eq + Hash, V> From<HashMap<K, V>> for HashMapCache<K, V> {
+    fn from(map: HashMap<K, V>) -> Self {
+        HashMapCache(map)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Clone + Eq + Hash, V: Clone> OperationCacheExt for WriteHandle<HashMapCache<K, V>> {
+    type Item = (K, V);
+    type Key = K;
+
+    fn insert(&mut self, (key, value): (K, V)) {
+        self.write(HashMapOperation::Insert(key, value))
+    }
+    fn remove(&mut self, key: K) {
+        self.write(HashMapOperation::Remove(key))
+    }
+    fn clear(&mut self) {
+        self.write(HashMapOperation::Clear)
+    }
+}
+
+/// A `HashSet` that can be stored in an `evc`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HashSetCache<T: Eq + Hash>(pub HashSet<T>);
+
+/// An operation on a `HashSetCache`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HashSetOperation<T> {
+    /// Insert a value, if it isn't present already.
+    Insert(T),
+    /// Remove a value.
+    Remove(T),
+    /// Remove all values.
+    Clear,
+}
+
+#[cfg(feature = "std")]
+impl<T: Clone + Eq + Hash> OperationCache for HashSetCache<T> {
+    type Operation = HashSetOperation<T>;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        match operation {
+            HashSetOperation::Insert(value) => {
+                self.0.insert(value);
+            }
+            HashSetOperation::Remove(value) => {
+                self.0.remove(&value);
+            }
+            HashSetOperation::Clear => self.0.clear(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Eq + Hash> Default for HashSetCache<T> {
+    fn default() -> Self {
+        HashSetCache(HashSet::new())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Eq + Hash> Deref for HashSetCache<T> {
+    type Target = HashSet<T>;
+
+    fn deref(&self) -> &HashSet<T> {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Eq + Hash> From<HashSet<T>> for HashSetCache<T> {
+    fn from(set: HashSet<T>) -> Self {
+        HashSetCache(set)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Clone + Eq + Hash> OperationCacheExt for WriteHandle<HashSetCache<T>> {
+    type Item = T;
+    type Key = T;
+
+    fn insert(&mut self, value: T) {
+        self.write(HashSetOperation::Insert(value))
+    }
+    fn remove(&mut self, value: T) {
+        self.write(HashSetOperation::Remove(value))
+    }
+    fn clear(&mut self) {
+        self.write(HashSetOperation::Clear)
+    }
+}
@@ -0,0 +1,55 @@
+//! The counters readers use to tell the writer whether, and since when, they are reading. The
+//! most significant bit of a counter is set while its reader isn't reading.
+
+#[cfg(target_has_atomic = "64")]
+use crate::sync::atomic::AtomicU64;
+use crate::sync::atomic::{AtomicU32, AtomicUsize};
+
+/// An atomic unsigned integer that can be used as an epoch counter.
+pub(crate) trait AtomicUnsigned {
+    /// The integer stored in the atomic.
+    type Value;
+    /// The most significant bit of the integer, marking that the reader isn't reading.
+    const MSB: Self::Value;
+}
+
+macro_rules! atomic_unsigned {
+    ($atomic:ty, $value:ty) => {
+        impl AtomicUnsigned for $atomic {
+            type Value = $value;
+            const MSB: $value = 1 << (<$value>::BITS - 1);
+        }
+    };
+}
+
+atomic_unsigned!(AtomicU32, u32);
+#[cfg(target_has_atomic = "64")]
+atomic_unsigned!(AtomicU64, u64);
+atomic_unsigned!(AtomicUsize, usize);
+
+/// The atomic used for epochs, chosen by the `epoch-u32` and `epoch-u64` features. The wider one
+/// wins if both are enabled, so that the features stay additive.
+#[cfg(all(feature = "epoch-u32", not(feature = "epoch-u64")))]
+pub(crate) type AtomicEpoch = AtomicU32;
+#[cfg(feature = "epoch-u64")]
+pub(crate) type AtomicEpoch = AtomicU64;
+#[cfg(not(any(feature = "epoch-u32", feature = "epoch-u64")))]
+pub(crate) type AtomicEpoch = AtomicUsize;
+
+pub(crate) type EpochValue = <AtomicEpoch as AtomicUnsigned>::Value;
+
+pub(crate) const EPOCH_MSB: EpochValue = <AtomicEpoch as AtomicUnsigned>::MSB;
+
+/// Get the epoch a reader uses for its next read. Epochs wrap around before reaching the most
+/// significant bit, skipping zero, which marks readers that have never read.
+///
+/// Wrapping is safe, since the writer only waits while a reader's epoch stays equal to the one it
+/// recorded after the last swap. At worst, a reader that wrapped all the way around to that epoch
+/// makes the writer wait for it to leave once more.
+pub(crate) fn next_epoch(epoch: EpochValue) -> EpochValue {
+    if epoch + 1 == EPOCH_MSB {
+        1
+    } else {
+        epoch + 1
+    }
+}
@@ -1,4 +1,5 @@
 #![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
 //! A lock-free (when reading), eventually consistent synchronization primitive.
 //!
@@ -10,6 +11,7 @@
 //! able to cache all possible mutations on the inner type (`OperationCache`). Therefore making
 //! an extension trait and implementing it for `WriteHandle<YourType>` is encouraged, so that
 //! accessing the inner data can be done using regular methods (like `evmap` does internally).
+//! Wrappers for the most common containers are already provided in the `containers` module.
 //!
 //! # Examples
 //!
@@ -66,50 +68,391 @@
 //! assert_eq!(r_handle.read().0, &[]);
 //!
 //! ```
+//!
+//! # Features
+//!
+//! * `std` (enabled by default): use the `std` mutex, and yield to other threads while waiting for
+//!   readers. Required for `WriteHandle::refresh_timeout`.
+//! * `map` (enabled by default): add the `map` module, a ready-made wrapper around `HashMap`.
+//!   Requires `std`.
+//! * `epoch-u32`, `epoch-u64`: use 32 or 64 bit counters for the epochs of readers, instead of
+//!   pointer-sized ones. 64 bit counters are used if both are enabled.
+//! * `parking_lot`: use the `parking_lot` mutex instead of the `std` one, which is faster under
+//!   contention and never poisoned. Requires `std`.
+//! * `spin`: use a spinlock instead, which makes the crate `no_std` (only requiring `alloc`) when
+//!   `std` is disabled.
+//! * `serde`: implement `Serialize` for read guards, and allow deserializing into a new `evc`.
+//! * `futures`: add `ReadHandle::version_stream`, a `Stream` of the versions published by the
+//!   writer.
+//! * `derive`: add `#[derive(OperationCache)]`, which implements `OperationCache` for a type from
+//!   an enum of its operations, see the `evc-derive` crate.
+//! * `tokio`: add `WriteHandle::refresh_async`, which yields to the Tokio executor while waiting.
+
+extern crate alloc;
+
+#[cfg(not(any(feature = "std", feature = "spin")))]
+compile_error!("either the `std` or the `spin` feature has to be enabled");
+
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::Deref;
+use core::ptr;
+
+mod backoff;
+mod epoch;
+use epoch::AtomicEpoch;
+mod sync;
+use sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use sync::Mutex;
+
+pub mod containers;
+#[cfg(feature = "map")]
+pub mod map;
 
-use std::mem;
-use std::sync::atomic::{AtomicPtr, AtomicUsize};
-use std::sync::{Arc, Mutex, Weak};
+mod builder;
+pub use builder::Builder;
+
+mod shared;
+pub use shared::SharedWriteHandle;
+
+#[cfg(feature = "derive")]
+pub use evc_derive::OperationCache;
 
 mod read;
-pub use read::{ReadHandle, ReadHandleFactory, ReadHandleGuard};
+pub use read::{
+    MappedReadHandleGuard, Pinned, ReadHandle, ReadHandleFactory, ReadHandleGuard, WeakReadHandle,
+};
+
+#[cfg(feature = "std")]
+pub use read::TimedReadHandleGuard;
 
 mod write;
-pub use write::WriteHandle;
+pub use write::{
+    IntoInnerError, RefreshError, RefreshGuard, RefreshStats, SyncStrategy, WriteHandle,
+};
+
+#[cfg(feature = "futures")]
+mod stream;
+#[cfg(feature = "futures")]
+pub use stream::VersionStream;
 
-pub(crate) type Epoch = Arc<AtomicUsize>;
-pub(crate) type WeakEpoch = Weak<AtomicUsize>;
-pub(crate) type Epochs = Arc<Mutex<Vec<WeakEpoch>>>;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(feature = "serde")]
+pub use serde_impls::from_deserializer;
+
+pub(crate) type Epoch = Arc<ReaderEpoch>;
+pub(crate) type WeakEpoch = Weak<ReaderEpoch>;
+pub(crate) type Epochs = Arc<Mutex<Readers>>;
+
+/// The epoch of a reader, and the index of its slot in the epochs, which changes when the writer
+/// removes dropped readers before it. The slot is only accessed with the epochs locked.
+#[derive(Debug, Default)]
+pub(crate) struct ReaderEpoch {
+    epoch: AtomicEpoch,
+    pub(crate) slot: AtomicUsize,
+}
+
+impl Deref for ReaderEpoch {
+    type Target = AtomicEpoch;
+
+    fn deref(&self) -> &AtomicEpoch {
+        &self.epoch
+    }
+}
+
+/// Get the id of a reader, which is the address of its epoch.
+pub(crate) fn epoch_id(epoch: &Epoch) -> usize {
+    Arc::as_ptr(epoch) as usize
+}
+
+/// The epochs of the readers, and the slots in them that dropped readers have left for new ones.
+#[derive(Default)]
+pub(crate) struct Readers {
+    pub(crate) epochs: Vec<WeakEpoch>,
+    pub(crate) vacant: Vec<usize>,
+    // Set when the writer has been dropped, after which there are no refreshes to remove the
+    // dropped readers, and they're removed when vacating instead.
+    pub(crate) writer_dropped: bool,
+}
+
+impl Readers {
+    /// Register the epoch of a new reader, taking a vacant slot if there is one.
+    pub(crate) fn register(&mut self, epoch: &Epoch) {
+        let weak_epoch = Arc::downgrade(epoch);
+
+        // The writer may have to wait for this reader if the dropped one was reading during the
+        // last refresh, and this reader reaches the same epoch, which is still safe.
+        let slot = match self.vacant.pop() {
+            Some(slot) => {
+                self.epochs[slot] = weak_epoch;
+                slot
+            }
+            None => {
+                self.epochs.push(weak_epoch);
+                self.epochs.len() - 1
+            }
+        };
+        epoch.slot.store(slot, Ordering::Relaxed);
+    }
+    /// Get the number of registered epochs, which includes the ones pooled by factories.
+    pub(crate) fn registered(&self) -> usize {
+        self.epochs.len() - self.vacant.len()
+    }
+    /// Remove the vacant slots, telling the readers that are moved their new slots.
+    pub(crate) fn compact(&mut self) {
+        self.vacant.clear();
+        let mut live_readers = 0;
+
+        for index in 0..self.epochs.len() {
+            if let Some(epoch) = self.epochs[index].upgrade() {
+                epoch.slot.store(live_readers, Ordering::Relaxed);
+                self.epochs.swap(live_readers, index);
+                live_readers += 1;
+            }
+        }
+        self.epochs.truncate(live_readers);
+    }
+    /// Leave the slot of a reader that is being dropped for the next new reader.
+    pub(crate) fn vacate(&mut self, epoch: &Epoch) {
+        let slot = epoch.slot.load(Ordering::Relaxed);
+        debug_assert!(ptr::eq(self.epochs[slot].as_ptr(), Arc::as_ptr(epoch)));
+
+        if !self.writer_dropped {
+            self.epochs[slot] = Weak::new();
+            self.vacant.push(slot);
+            return;
+        }
+        // The writer left no vacant slots, and with none of them, the order doesn't matter.
+        self.epochs.swap_remove(slot);
+        if let Some(moved) = self.epochs.get(slot).and_then(Weak::upgrade) {
+            moved.slot.store(slot, Ordering::Relaxed);
+        }
+        if self.epochs.len() <= self.epochs.capacity() / 4 {
+            self.epochs.shrink_to(self.epochs.len() * 2);
+        }
+    }
+}
 
 /// Represents anything that can be mutated using operations. This trait has to be implemented in
 /// order to store it in an `evc`.
+///
+/// # Replaying operations
+///
+/// Both buffers have to end up with the same contents, so every operation is applied twice: once
+/// to the writer's buffer before it's published, and once to the other buffer after the readers
+/// have left it. This is why operations have to be `Clone`. For `Copy` operations, cloning is
+/// just a copy, so there is no separate path for them.
+///
+/// Replaying is cheap when operations are small compared to the data, e.g. an insert into a large
+/// map. It is wasteful when the operations are expensive to apply or to clone, but make up most
+/// of the data anyway. There, applying them once and cloning the published buffer into the other
+/// one may be faster, which `WriteHandle::set_sync_strategy` allows. Operations that can't be
+/// cloned at all would need that strategy, but since refreshing is generic over both, the bound
+/// can't be dropped for some implementors only.
 pub trait OperationCache {
     /// The operation this type uses for modifying itself.
     type Operation: Clone;
 
     /// Apply an operation to self.
     fn apply_operation(&mut self, operations: Self::Operation);
+
+    /// Apply multiple operations to self, in order, e.g. the ones from `WriteHandle::oplog` to a
+    /// replica. Refreshing uses `apply_operations_seq` instead.
+    fn apply_operations(&mut self, operations: &[Self::Operation]) {
+        for operation in operations.iter().cloned() {
+            self.apply_operation(operation);
+        }
+    }
+
+    /// Apply an operation to self, along with its sequence number. Calls `apply_operation` by
+    /// default.
+    ///
+    /// Sequence numbers are assigned when operations are written, counting from 0, and an
+    /// operation is applied with the same one in both buffers. Operations that are discarded,
+    /// rolled back or rejected by `WriteHandle::try_refresh` leave gaps. Sorting by `order_key`
+    /// moves the numbers along with the operations, so they may be applied out of order. If
+    /// `compact` removes operations, the ones it leaves take the numbers of the last ones, and
+    /// operations it adds get new ones.
+    fn apply_operation_seq(&mut self, operation: Self::Operation, _seq: u64) {
+        self.apply_operation(operation);
+    }
+
+    /// Apply multiple operations to self, in order, along with their sequence numbers. This is
+    /// what refreshing uses, and can be overridden when applying many operations at once can be
+    /// done more efficiently. Calls `apply_operation_seq` for each operation by default.
+    fn apply_operations_seq(&mut self, operations: &[Self::Operation], seqs: &[u64]) {
+        for (operation, &seq) in operations.iter().cloned().zip(seqs) {
+            self.apply_operation_seq(operation, seq);
+        }
+    }
+
+    /// Compact the operations before they are applied, e.g. by removing operations that are
+    /// overridden by later ones. Since the operations are applied to both buffers, this can save
+    /// twice the work. This must not change the result of applying the operations. Does nothing by
+    /// default.
+    fn compact(_operations: &mut Vec<Self::Operation>) {}
+
+    /// Get the priority of an operation. Refreshing sorts the pending operations by it before
+    /// `compact`, so that operations with lower keys are applied first, e.g. removals before
+    /// insertions. The sort is stable, so operations with equal keys keep the order they were
+    /// written in, and they're never moved across closures passed to `WriteHandle::absorb`.
+    /// Returns the same key for every operation by default, which never reorders them.
+    fn order_key(_operation: &Self::Operation) -> u8 {
+        0
+    }
+}
+
+/// An `OperationCache` whose operations can fail, so that invalid operations can be rejected by
+/// `WriteHandle::try_refresh` instead of panicking halfway through a refresh.
+pub trait TryOperationCache: OperationCache {
+    /// The error returned when an operation can't be applied.
+    type Error;
+
+    /// Try to apply an operation to self. If this fails, self must be left unmodified, and if it
+    /// succeeds, it must have had the same effect as `apply_operation`.
+    fn try_apply_operation(&mut self, operation: Self::Operation) -> Result<(), Self::Error> {
+        self.apply_operation(operation);
+        Ok(())
+    }
+
+    /// Try to apply an operation to self along with its sequence number, like `try_apply_operation`
+    /// but with the effect of `apply_operation_seq` on success. This is what
+    /// `WriteHandle::try_refresh` uses, and calls `try_apply_operation` by default.
+    fn try_apply_operation_seq(
+        &mut self,
+        operation: Self::Operation,
+        _seq: u64,
+    ) -> Result<(), Self::Error> {
+        self.try_apply_operation(operation)
+    }
 }
 
 pub(crate) struct Inner<T> {
     value: T,
+    version: usize,
+}
+
+/// A shared pointer to one of the two buffers, freeing the buffer when the last handle using the
+/// pointer is dropped.
+///
+/// The writer holds the only reference to the pointer of its own buffer, and the readers and
+/// factories share the pointer to theirs along with the writer. Swapping the buffers only swaps
+/// the pointees, so each buffer is freed exactly once, by whichever pointer it ends up in:
+/// the writer's when the writer is dropped (after waiting for the readers to leave it), and the
+/// readers' when the last of the writer, the readers and the factories is dropped.
+///
+/// The pointer to the readers' buffer also keeps track of the number of refreshes that have been
+/// published, which outlives the writer.
+///
+/// Only the writer accesses the pointer to its own buffer, so it's always loaded and swapped with
+/// `Relaxed`. The readers synchronize with the writer through the pointer to their buffer and the
+/// epochs instead.
+///
+/// Unlike `AtomicPtr`, the pointer is only `Send` and `Sync` if the buffer is. The handles share
+/// it between threads, which lets them read the buffer from any thread, and drop it on any
+/// thread.
+pub(crate) struct InnerPtr<T> {
+    pointer: AtomicPtr<Inner<T>>,
+    version: AtomicUsize,
+    // The epochs of the readers that have read for the first time since the writer last swapped
+    // the buffers, which the writer then starts to check. Readers that have never read can only
+    // read the latest buffer, so they cost nothing to refresh. `None` once the writer is dropped.
+    activated: Mutex<Option<Vec<WeakEpoch>>>,
+    #[cfg(feature = "futures")]
+    wakers: stream::Wakers,
+
+    _owns: PhantomData<T>,
 }
 
-pub(crate) const USIZE_MSB: usize = 1 << (mem::size_of::<usize>() * 8 - 1);
+impl<T> InnerPtr<T> {
+    fn new(value: T) -> Self {
+        InnerPtr {
+            pointer: AtomicPtr::new(Box::into_raw(Box::new(Inner { value, version: 0 }))),
+            version: AtomicUsize::new(0),
+            activated: Mutex::new(Some(Vec::new())),
+            #[cfg(feature = "futures")]
+            wakers: stream::Wakers::default(),
+
+            _owns: PhantomData,
+        }
+    }
+    fn into_value(self) -> T {
+        let pointer = self.pointer.swap(ptr::null_mut(), Ordering::Relaxed);
+        unsafe { Box::from_raw(pointer) }.value
+    }
+}
+impl<T> Deref for InnerPtr<T> {
+    type Target = AtomicPtr<Inner<T>>;
+    fn deref(&self) -> &Self::Target {
+        &self.pointer
+    }
+}
+impl<T> Drop for InnerPtr<T> {
+    fn drop(&mut self) {
+        let pointer = self.pointer.load(Ordering::Relaxed);
+
+        if !pointer.is_null() {
+            mem::drop(unsafe { Box::from_raw(pointer) });
+        }
+    }
+}
 
 /// Create a write handle and a read handle to some data. The data must be both `OperationCache`,
 /// to support queuing data (so that both buffers can be modified during refreshes), and `Clone`,
 /// to make double buffering possible.
 pub fn new<T: Clone + OperationCache>(value: T) -> (WriteHandle<T>, ReadHandle<T>) {
-    let readers_inner = Arc::new(AtomicPtr::new(Box::into_raw(Box::new(Inner {
-        value: value.clone(),
-    }))));
-    let writers_inner = Arc::new(AtomicPtr::new(Box::into_raw(Box::new(Inner { value }))));
+    new_with_capacity(value, 0)
+}
 
-    let epochs = Arc::new(Mutex::new(Vec::new()));
+/// Create a write handle and a read handle to the default value of a type, like `new`.
+pub fn default<T: Default + Clone + OperationCache>() -> (WriteHandle<T>, ReadHandle<T>) {
+    new(T::default())
+}
+
+/// Create a write handle and a read handle to some data like `new`, with room for `ops_capacity`
+/// operations to be queued before the operation buffer has to reallocate. `Builder` allows
+/// configuring more than that.
+pub fn new_with_capacity<T: Clone + OperationCache>(
+    value: T,
+    ops_capacity: usize,
+) -> (WriteHandle<T>, ReadHandle<T>) {
+    new_from_pair_with_capacity(value.clone(), value, ops_capacity)
+}
+
+/// Create a write handle and a read handle from two buffers, without cloning, which also lifts the
+/// `Clone` bound of `new`. The first is published to the readers, and the second is the writer's.
+///
+/// The two buffers must be observably equal, since the operations of each refresh are only
+/// replayed on top of them; otherwise readers will see the buffers diverge after the first
+/// refresh.
+pub fn new_from_pair<T: OperationCache>(readers: T, writers: T) -> (WriteHandle<T>, ReadHandle<T>) {
+    new_from_pair_with_capacity(readers, writers, 0)
+}
+
+fn new_from_pair_with_capacity<T: OperationCache>(
+    readers: T,
+    writers: T,
+    ops_capacity: usize,
+) -> (WriteHandle<T>, ReadHandle<T>) {
+    let readers_inner = Arc::new(InnerPtr::new(readers));
+    let writers_inner = Arc::new(InnerPtr::new(writers));
+
+    let epochs = Arc::new(Mutex::new(Readers::default()));
 
     let read_handle = ReadHandle::new(Arc::clone(&readers_inner), Arc::clone(&epochs));
-    let write_handle = WriteHandle::new(writers_inner, readers_inner, epochs);
+    let write_handle = WriteHandle::new(writers_inner, readers_inner, epochs, ops_capacity);
 
     (write_handle, read_handle)
 }
+
+/// Create a write handle that can be cloned and shared between multiple writers, and a read handle,
+/// to some data. See `SharedWriteHandle` for when this is worth the locking, and `new` for the
+/// requirements on the data.
+pub fn new_shared<T: Clone + OperationCache>(value: T) -> (SharedWriteHandle<T>, ReadHandle<T>) {
+    let (write_handle, read_handle) = new(value);
+    (write_handle.into(), read_handle)
+}
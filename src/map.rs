@@ -0,0 +1,113 @@
+//! A hash map that can be read while it's being written to, like
+//! [`evmap`](https://docs.rs/evmap).
+//!
+//! ```
+//! let (mut w_handle, r_handle) = evc::map::new();
+//!
+//! w_handle.insert("answer", 42);
+//! assert!(r_handle.get("answer").is_none());
+//!
+//! w_handle.refresh();
+//! assert_eq!(r_handle.get("answer").as_deref(), Some(&42));
+//! ```
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::Hash;
+use core::ops::{Deref, DerefMut};
+
+use crate::containers::{HashMapCache, HashMapOperation};
+use crate::{MappedReadHandleGuard, ReadHandle, WriteHandle};
+
+/// The map stored in the `evc`, which read guards dereference to.
+pub type MapCache<K, V> = HashMapCache<K, V>;
+
+/// Create a new, empty map, returning a handle for writing to it and a handle for reading it.
+pub fn new<K: Clone + Eq + Hash, V: Clone>() -> (MapHandle<K, V>, ReadHandle<MapCache<K, V>>) {
+    let (handle, r_handle) = crate::new(MapCache::default());
+    (MapHandle { handle }, r_handle)
+}
+
+/// A handle for writing to a map. It dereferences to the underlying `WriteHandle`, which is used
+/// for refreshing.
+pub struct MapHandle<K: Clone + Eq + Hash, V: Clone> {
+    handle: WriteHandle<MapCache<K, V>>,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> MapHandle<K, V> {
+    /// Queue inserting a value, replacing the previous value of the key.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.handle.write(HashMapOperation::Insert(key, value))
+    }
+    /// Queue removing the value of a key.
+    pub fn remove(&mut self, key: K) {
+        self.handle.write(HashMapOperation::Remove(key))
+    }
+    /// Queue removing all keys.
+    pub fn clear(&mut self) {
+        self.handle.write(HashMapOperation::Clear)
+    }
+    /// Get the published value of a key, without any pending operations.
+    pub fn get<Q: ?Sized + Eq + Hash>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.handle.published().get(key)
+    }
+    /// Check whether a key has a published value, without any pending operations.
+    pub fn contains_key<Q: ?Sized + Eq + Hash>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.handle.published().contains_key(key)
+    }
+    /// Consume this handle, returning the underlying `WriteHandle`.
+    pub fn into_write_handle(self) -> WriteHandle<MapCache<K, V>> {
+        self.handle
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Deref for MapHandle<K, V> {
+    type Target = WriteHandle<MapCache<K, V>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> DerefMut for MapHandle<K, V> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.handle
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> fmt::Debug for MapHandle<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MapHandle").field(&self.handle).finish()
+    }
+}
+
+impl<K: Eq + Hash, V> ReadHandle<MapCache<K, V>> {
+    /// Get a guard to the value of a key, if it has one.
+    pub fn get<Q: ?Sized + Eq + Hash>(&self, key: &Q) -> Option<MappedReadHandleGuard<'_, V>>
+    where
+        K: Borrow<Q>,
+    {
+        self.read().try_map(|map| map.get(key))
+    }
+    /// Check whether a key has a value.
+    pub fn contains_key<Q: ?Sized + Eq + Hash>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.read().contains_key(key)
+    }
+    /// Get the number of keys in the map.
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+    /// Check whether the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+}
@@ -1,60 +1,310 @@
-use std::cell::Cell;
-use std::marker::PhantomData;
-use std::mem;
-use std::ops::Deref;
-use std::ptr;
-use std::sync::atomic;
-use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
-use std::sync::Arc;
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::Deref;
+#[cfg(feature = "std")]
+use core::time::Duration;
 
-use crate::{Epoch, Epochs, Inner, USIZE_MSB};
+use crate::backoff::Backoff;
+use crate::epoch::{self, AtomicEpoch, EpochValue, EPOCH_MSB};
+use crate::sync::atomic::{self, Ordering};
+use crate::sync::{self, Mutex};
+use crate::{Epoch, Epochs, Inner, InnerPtr, ReaderEpoch, Readers};
+
+/// The epochs of dropped handles that were created by a factory, kept registered so that new
+/// handles can reuse them.
+type Pool = Arc<Mutex<Vec<Epoch>>>;
+
+/// A closure passed to `ReadHandle::set_on_advance`.
+type OnAdvance = Box<dyn Fn(u64) + Send + Sync>;
 
 /// A handle used for accessing data immutably using RAII guards.
+///
+/// Creating a handle, including by cloning, allocates its epoch and registers it with the writer,
+/// which has to lock the epochs. Dropping it locks them again, leaving its slot for the next new
+/// handle. Handles created by a `ReadHandleFactory` avoid this by reusing the epochs of dropped
+/// handles from the same factory.
 pub struct ReadHandle<T> {
-    inner: Option<Arc<AtomicPtr<Inner<T>>>>,
+    inner: Option<Arc<InnerPtr<T>>>,
     epochs: Option<Epochs>,
+    pool: Option<Pool>,
 
     global_epoch: Epoch,
-    local_epoch: AtomicUsize,
+    local_epoch: AtomicEpoch,
+    guards: Cell<usize>,
+    last_version: Cell<Option<u64>>,
+    on_advance: RefCell<Option<OnAdvance>>,
 
     _not_sync: PhantomData<Cell<()>>,
 }
 impl<T> ReadHandle<T> {
-    pub(crate) fn new(inner: Arc<AtomicPtr<Inner<T>>>, epochs: Epochs) -> Self {
-        let global_epoch = Arc::new(AtomicUsize::new(0));
-        epochs.lock().unwrap().push(Arc::downgrade(&global_epoch));
+    /// Create a handle that takes the slot a dropped reader left in the epochs if there is one,
+    /// so that creating and dropping readers between refreshes doesn't make the epochs grow.
+    pub(crate) fn new(inner: Arc<InnerPtr<T>>, epochs: Epochs) -> Self {
+        let global_epoch = Arc::new(ReaderEpoch::default());
+        // The epochs are still valid if a refresh panicked while holding the lock.
+        sync::lock(&epochs).register(&global_epoch);
+
+        Self::with_epoch(inner, epochs, global_epoch)
+    }
+    /// Create a handle that reuses an epoch from the pool if there is one, and returns its epoch
+    /// to the pool when dropped.
+    fn pooled(inner: Arc<InnerPtr<T>>, epochs: Epochs, pool: Pool) -> Self {
+        let pooled_epoch = sync::lock(&pool).pop();
+
+        let mut handle = match pooled_epoch {
+            Some(global_epoch) => Self::with_epoch(inner, epochs, global_epoch),
+            None => Self::new(inner, epochs),
+        };
+        handle.pool = Some(pool);
+        handle
+    }
+    fn with_epoch(inner: Arc<InnerPtr<T>>, epochs: Epochs, global_epoch: Epoch) -> Self {
+        // A reused epoch continues where its previous handle stopped, so that the writer doesn't
+        // mistake this handle for still reading at the same epoch.
+        let local_epoch = global_epoch.load(Ordering::Relaxed) & !EPOCH_MSB;
 
         Self {
             inner: Some(inner),
             epochs: Some(epochs),
+            pool: None,
 
             global_epoch,
-            local_epoch: AtomicUsize::new(0),
+            local_epoch: AtomicEpoch::new(local_epoch),
+            guards: Cell::new(0),
+            last_version: Cell::new(None),
+            on_advance: RefCell::new(None),
 
             _not_sync: PhantomData,
         }
     }
 
     /// Create a RAII guard that allows reading the inner value directly.
+    ///
+    /// Multiple guards from the same handle may be alive at the same time. Nested guards reuse the
+    /// epoch of the outermost guard, so the writer will wait until all of them have been dropped,
+    /// but they may observe a newer version than the guards created before them.
     pub fn read(&'_ self) -> ReadHandleGuard<'_, T> {
-        let epoch = self.local_epoch.fetch_add(1, Ordering::Relaxed) + 1;
-        self.global_epoch.store(epoch, Ordering::Release);
+        self.enter(true)
+    }
+    /// Create a guard like `read`, but without the fence that synchronizes with a writer that is
+    /// refreshing on another thread at the same time, which is costly on some architectures.
+    ///
+    /// # Safety
+    ///
+    /// Every refresh must either happen before this call or after it, e.g. because the writer is
+    /// on the same thread, or is only used while holding a lock that this call also holds. A
+    /// refresh running concurrently may otherwise miss this reader, and mutate the buffer the
+    /// guard is reading.
+    pub unsafe fn read_unsynchronized(&'_ self) -> ReadHandleGuard<'_, T> {
+        self.enter(false)
+    }
+    fn enter(&'_ self, synchronized: bool) -> ReadHandleGuard<'_, T> {
+        let guards = self.guards.get();
+
+        let epoch = if guards == 0 {
+            // Only this handle uses its local epoch.
+            let last_epoch = self.local_epoch.load(Ordering::Relaxed);
+            let epoch = epoch::next_epoch(last_epoch);
+            debug_assert_eq!(epoch & EPOCH_MSB, 0);
+            self.local_epoch.store(epoch, Ordering::Relaxed);
+            // Releasing the new epoch makes the reads of the previous guards happen before a
+            // writer that acquires it, and thus knows that they are done.
+            self.global_epoch.store(epoch, Ordering::Release);
+
+            if last_epoch == 0 {
+                // The writer only checks the readers that have read. Either it takes this epoch
+                // after the next swap, and sees the new epoch, or it swapped before this, in which
+                // case the mutex makes the load below see the swapped pointer.
+                if let Some(ref mut activated) =
+                    *sync::lock(&self.inner.as_ref().unwrap().activated)
+                {
+                    // Without refreshes, the readers that were dropped since would pile up.
+                    if activated.len() == activated.capacity() {
+                        activated.retain(|epoch| epoch.strong_count() != 0);
+                    }
+                    activated.push(Arc::downgrade(&self.global_epoch));
+                }
+            }
 
-        atomic::fence(Ordering::SeqCst);
+            // Pairs with the fence after the writer swaps the buffers: either the writer sees the
+            // new epoch and waits for it, or the load below sees the swapped pointer.
+            if synchronized {
+                atomic::fence(Ordering::SeqCst);
+            }
+            epoch
+        } else {
+            self.local_epoch.load(Ordering::Relaxed)
+        };
+        self.guards.set(guards + 1);
 
+        // The readers' buffer is only taken out of its pointer once there are no other handles to
+        // it, so it can never be null here. Acquiring the pointer makes the writes to the buffer
+        // before it was published visible.
         let pointer = self.inner.as_ref().unwrap().load(Ordering::Acquire);
+        debug_assert!(!pointer.is_null());
 
-        ReadHandleGuard {
+        let guard = ReadHandleGuard {
             handle: self,
             pointer,
             epoch,
+        };
+        // The guard leaves the epoch if the closure set by `set_on_advance` panics.
+        self.observe(unsafe { (*pointer).version } as u64);
+        guard
+    }
+    /// Call a closure with the version whenever this handle reads a newer version than it read
+    /// last, e.g. to invalidate what was computed from the older one. The closure isn't called on
+    /// the first read. Replaces the previous closure, if any; clones of this handle start without
+    /// one.
+    ///
+    /// The closure runs inside the read, with the epoch already taken, so it may read from this
+    /// handle again, but it must not set a new closure.
+    pub fn set_on_advance<F: Fn(u64) + Send + Sync + 'static>(&self, f: F) {
+        *self.on_advance.borrow_mut() = Some(Box::new(f));
+    }
+    /// Record the version that was just read, calling the closure set by `set_on_advance` if it's
+    /// newer than the previous one.
+    fn observe(&self, version: u64) {
+        let last_version = self.last_version.replace(Some(version));
+
+        if matches!(last_version, Some(last_version) if last_version < version) {
+            if let Some(ref on_advance) = *self.on_advance.borrow() {
+                on_advance(version);
+            }
         }
     }
+    /// Take the epoch once for many reads, each of which gets the latest published value without
+    /// touching the epoch again. The epoch is left when the returned `Pinned` is dropped.
+    ///
+    /// This trades writer latency for reader throughput: while pinned, the writer can refresh at
+    /// most once more, and then blocks until the `Pinned` is dropped. Keep it for short batches
+    /// of reads.
+    pub fn pin(&self) -> Pinned<'_, T> {
+        Pinned { guard: self.read() }
+    }
+    /// Create a RAII guard like `read`, which leaves the epoch and takes it again when `renew` is
+    /// called after the timeout, so that the writer can refresh.
+    ///
+    /// This only matters for readers that would otherwise hold a guard for a long time, e.g.
+    /// during a long loop of reads, since the writer can't refresh more than once while a guard
+    /// is held.
+    #[cfg(feature = "std")]
+    pub fn read_timeout(&self, timeout: Duration) -> TimedReadHandleGuard<'_, T> {
+        TimedReadHandleGuard {
+            handle: self,
+            guard: Some(self.read()),
+            timeout,
+            deadline: sync::Deadline::now() + timeout,
+        }
+    }
+    /// Clone the inner value, keeping the epoch only while cloning.
+    pub fn read_owned(&self) -> T
+    where
+        T: Clone,
+    {
+        self.read().clone_inner()
+    }
+    /// Clone the inner value along with its version, unless the latest published version is still
+    /// `last_version`, e.g. as returned by an earlier call. Versions published in between aren't
+    /// returned separately.
+    pub fn read_cloned_if_changed(&self, last_version: u64) -> Option<(u64, T)>
+    where
+        T: Clone,
+    {
+        // Checking the version first doesn't touch the epoch.
+        if self.version() == last_version {
+            return None;
+        }
+        let guard = self.read();
+        let version = guard.version();
+
+        if version == last_version {
+            None
+        } else {
+            Some((version, guard.clone_inner()))
+        }
+    }
+    /// Read the inner value using a closure, keeping the epoch only while the closure runs.
+    pub fn read_map<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        let guard = self.read();
+        f(&guard)
+    }
+    /// Create a RAII guard like `read`, returning `None` if the readers' buffer is missing.
+    ///
+    /// Since readers keep the readers' buffer alive, even after the writer has been dropped or
+    /// consumed using `WriteHandle::into_inner`, this currently always returns `Some`.
+    pub fn read_opt(&'_ self) -> Option<ReadHandleGuard<'_, T>> {
+        // Checked before reading, which dereferences the pointer. The pointer is only compared
+        // here, and `read` acquires it again.
+        let pointer = self.inner.as_ref().unwrap().load(Ordering::Relaxed);
+
+        if pointer.is_null() {
+            None
+        } else {
+            Some(self.read())
+        }
+    }
+    /// Block until the writer has published a version that this handle hasn't read yet, and create
+    /// a RAII guard to it. Returns immediately if a refresh has happened since the last read.
+    ///
+    /// This never returns if the writer doesn't refresh again.
+    pub fn wait_for_refresh(&'_ self) -> ReadHandleGuard<'_, T> {
+        let mut backoff = Backoff::new();
+
+        while !self.is_stale() {
+            backoff.snooze();
+        }
+        self.read()
+    }
+    /// Check whether the writer has published a newer version than the one this handle last read,
+    /// or if it hasn't read at all yet.
+    ///
+    /// This is only a hint, since the writer may publish a new version right after this returns.
+    pub fn is_stale(&self) -> bool {
+        self.last_version.get() != Some(self.version())
+    }
+    /// Get the version of the data most recently published by the writer, which is the number of
+    /// refreshes that have happened so far.
+    pub fn version(&self) -> u64 {
+        self.inner.as_ref().unwrap().version.load(Ordering::Acquire) as u64
+    }
+    /// Get an id identifying this handle among the live readers, as returned by
+    /// `WriteHandle::stuck_readers`. Handles created by a factory reuse the ids of the dropped
+    /// handles whose epochs they reuse.
+    pub fn id(&self) -> usize {
+        crate::epoch_id(&self.global_epoch)
+    }
+    /// Check whether both handles read the same data, i.e. were created from the same call to
+    /// `evc::new`, without reading it.
+    pub fn ptr_eq(&self, other: &ReadHandle<T>) -> bool {
+        Arc::ptr_eq(self.inner.as_ref().unwrap(), other.inner.as_ref().unwrap())
+    }
+    /// Create a stream of the versions published by the writer from now on.
+    #[cfg(feature = "futures")]
+    pub fn version_stream(&self) -> crate::VersionStream<T> {
+        crate::VersionStream::new(Arc::clone(self.inner.as_ref().unwrap()))
+    }
     /// Create a factory, used to make more read handles.
     pub fn factory(&self) -> ReadHandleFactory<T> {
         ReadHandleFactory {
             inner: Arc::clone(self.inner.as_ref().unwrap()),
             epochs: Arc::clone(self.epochs.as_ref().unwrap()),
+            pool: Some(self.pool.clone().unwrap_or_default()),
+        }
+    }
+
+    /// Create a weak handle, which doesn't keep the data alive, and can be upgraded to a new
+    /// handle for as long as the writer or another handle or factory exists.
+    pub fn downgrade(&self) -> WeakReadHandle<T> {
+        WeakReadHandle {
+            inner: Arc::downgrade(self.inner.as_ref().unwrap()),
+            epochs: Arc::downgrade(self.epochs.as_ref().unwrap()),
         }
     }
 
@@ -62,19 +312,14 @@ impl<T> ReadHandle<T> {
     pub fn into_factory(mut self) -> ReadHandleFactory<T> {
         ReadHandleFactory {
             inner: self.inner.take().unwrap(),
-            epochs: self.epochs.take().unwrap(),
+            epochs: Arc::clone(self.epochs.as_ref().unwrap()),
+            pool: Some(self.pool.clone().unwrap_or_default()),
         }
     }
     /// Try to move out the inner value if no other readers exist.
     pub fn into_inner(mut self) -> Option<T> {
         let inner = self.inner.take().unwrap();
-
-        if Arc::strong_count(&inner) == 1 {
-            let readers_inner = inner.swap(ptr::null_mut(), Ordering::Relaxed);
-            Some(unsafe { Box::from_raw(readers_inner) }.value)
-        } else {
-            None
-        }
+        Arc::try_unwrap(inner).ok().map(InnerPtr::into_value)
     }
 }
 impl<T> From<ReadHandleFactory<T>> for ReadHandle<T> {
@@ -87,47 +332,245 @@ impl<T> From<ReadHandle<T>> for ReadHandleFactory<T> {
         handle.into_factory()
     }
 }
+impl<T> fmt::Debug for ReadHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadHandle")
+            .field("local_epoch", &self.local_epoch.load(Ordering::Relaxed))
+            .field("global_epoch", &self.global_epoch.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+impl<T> Clone for ReadHandle<T> {
+    fn clone(&self) -> Self {
+        let inner = Arc::clone(self.inner.as_ref().unwrap());
+        let epochs = Arc::clone(self.epochs.as_ref().unwrap());
+
+        match self.pool {
+            Some(ref pool) => ReadHandle::pooled(inner, epochs, Arc::clone(pool)),
+            None => ReadHandle::new(inner, epochs),
+        }
+    }
+}
 impl<T> Drop for ReadHandle<T> {
     fn drop(&mut self) {
-        if let Some(inner) = self.inner.take() {
-            if Arc::strong_count(&inner) == 1 {
-                let readers_inner = inner.swap(ptr::null_mut(), Ordering::Relaxed);
-                mem::drop(unsafe { Box::from_raw(readers_inner) });
+        // The writer finds dropped readers through `Weak`, which doesn't order the reads of this
+        // handle before the writer mutates the buffer again. Locking the epochs before the epoch
+        // can be dropped does, since the writer keeps them locked while refreshing.
+        let mut readers = self.epochs.as_ref().map(|epochs| sync::lock(epochs));
+
+        // No guards can be alive here, so the epoch is marked as not reading.
+        if let Some(pool) = self.pool.take() {
+            sync::lock(&pool).push(Arc::clone(&self.global_epoch));
+
+            if let Some(ref mut readers) = readers {
+                drop_pool(&pool, readers);
             }
+        } else if let Some(ref mut readers) = readers {
+            readers.vacate(&self.global_epoch);
         }
     }
 }
-impl<T> Clone for ReadHandle<T> {
-    fn clone(&self) -> Self {
-        ReadHandle::new(
-            Arc::clone(self.inner.as_ref().unwrap()),
-            Arc::clone(self.epochs.as_ref().unwrap()),
-        )
+
+/// Vacate the slots of the pooled epochs if this is the last handle or factory using the pool.
+/// New ones can't be created meanwhile, since that requires one of them.
+fn drop_pool(pool: &Pool, readers: &mut Readers) {
+    if Arc::strong_count(pool) == 1 {
+        for epoch in sync::lock(pool).drain(..) {
+            readers.vacate(&epoch);
+        }
     }
 }
 
 /// A factory for read handles, allows retrieving new `ReadHandle`s while still being `Sync`.
+///
+/// The epochs of dropped handles created by a factory or its clones are pooled, and reused by the
+/// next handles it creates. Pooled epochs stay registered with the writer until the factory and
+/// all of its handles have been dropped, and their slots are vacated then.
 pub struct ReadHandleFactory<T> {
-    inner: Arc<AtomicPtr<Inner<T>>>,
+    inner: Arc<InnerPtr<T>>,
     epochs: Epochs,
+    // Only `None` while dropping.
+    pool: Option<Pool>,
 }
 
+impl<T> Drop for ReadHandleFactory<T> {
+    fn drop(&mut self) {
+        // The pooled epochs may be dropped along with the factory, see `ReadHandle`'s `Drop`. The
+        // pool is released with the epochs still locked, so that if the last handle is dropped at
+        // the same time, one of them sees that it's the last one.
+        let mut readers = sync::lock(&self.epochs);
+        let pool = self.pool.take().unwrap();
+        drop_pool(&pool, &mut readers);
+        mem::drop(pool);
+    }
+}
 impl<T> ReadHandleFactory<T> {
+    pub(crate) fn new(inner: Arc<InnerPtr<T>>, epochs: Epochs) -> Self {
+        ReadHandleFactory {
+            inner,
+            epochs,
+            pool: Some(Pool::default()),
+        }
+    }
     /// Create a new handle.
     pub fn handle(&self) -> ReadHandle<T> {
-        ReadHandle::new(Arc::clone(&self.inner), Arc::clone(&self.epochs))
+        ReadHandle::pooled(
+            Arc::clone(&self.inner),
+            Arc::clone(&self.epochs),
+            Arc::clone(self.pool.as_ref().unwrap()),
+        )
+    }
+
+    /// Read the inner value once using a closure, without keeping a handle around. The read uses a
+    /// transient epoch, which takes the slot of a dropped reader if there is one, and is vacated
+    /// again as soon as the closure returns, so the writer doesn't keep waiting on it.
+    pub fn read_once<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        let handle = ReadHandle::new(Arc::clone(&self.inner), Arc::clone(&self.epochs));
+        let guard = handle.read();
+        f(&guard)
+    }
+
+    /// Check whether both factories create handles to the same data, like `ReadHandle::ptr_eq`.
+    pub fn ptr_eq(&self, other: &ReadHandleFactory<T>) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Get the number of epochs in the registry, including the slots left by dropped readers,
+    /// which new readers take first, e.g. to watch for readers that are leaked.
+    pub fn registered_epochs(&self) -> usize {
+        sync::lock(&self.epochs).epochs.len()
+    }
+    /// Get the number of epochs of readers that haven't been dropped, including the epochs pooled
+    /// by factories, like `WriteHandle::reader_count`.
+    pub fn live_epochs(&self) -> usize {
+        sync::lock(&self.epochs)
+            .epochs
+            .iter()
+            .filter(|epoch| epoch.strong_count() != 0)
+            .count()
     }
 
     /// Consume this factory, returning a handle.
     pub fn into_handle(self) -> ReadHandle<T> {
-        ReadHandle::new(self.inner, self.epochs)
+        self.handle()
+    }
+
+    /// Move this factory into an `Arc`, e.g. to store it in a `static` `OnceLock` and create
+    /// handles from anywhere. The factory owns the data, so it's `'static` whenever `T` is.
+    pub fn into_arc(self) -> Arc<Self> {
+        Arc::new(self)
+    }
+}
+impl<T> Clone for ReadHandleFactory<T> {
+    fn clone(&self) -> Self {
+        ReadHandleFactory {
+            inner: Arc::clone(&self.inner),
+            epochs: Arc::clone(&self.epochs),
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+/// A weak reference to the data of a read handle, created by `ReadHandle::downgrade`.
+///
+/// Unlike a `ReadHandle`, it has no epoch, so it's `Sync` and the writer never waits for it.
+pub struct WeakReadHandle<T> {
+    inner: Weak<InnerPtr<T>>,
+    epochs: Weak<Mutex<Readers>>,
+}
+impl<T> WeakReadHandle<T> {
+    /// Create a new handle, or return `None` if the writer and all handles and factories have been
+    /// dropped, along with the data.
+    pub fn upgrade(&self) -> Option<ReadHandle<T>> {
+        let inner = self.inner.upgrade()?;
+        let epochs = self.epochs.upgrade()?;
+
+        Some(ReadHandle::new(inner, epochs))
+    }
+}
+impl<T> Clone for WeakReadHandle<T> {
+    fn clone(&self) -> Self {
+        WeakReadHandle {
+            inner: Weak::clone(&self.inner),
+            epochs: Weak::clone(&self.epochs),
+        }
+    }
+}
+impl<T> fmt::Debug for WeakReadHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeakReadHandle")
+            .field("alive", &(self.inner.strong_count() != 0))
+            .finish()
+    }
+}
+
+/// A pinned epoch of a read handle, created by `ReadHandle::pin`.
+pub struct Pinned<'a, T> {
+    guard: ReadHandleGuard<'a, T>,
+}
+impl<T> Pinned<'_, T> {
+    /// Get the latest value published by the writer.
+    pub fn get(&self) -> &T {
+        let handle = self.guard.handle;
+
+        // Every buffer published while pinned is protected by the same epoch, since the writer
+        // waits for it to change before mutating any of them.
+        let pointer = handle.inner.as_ref().unwrap().load(Ordering::Acquire);
+        handle.observe(unsafe { (*pointer).version } as u64);
+
+        unsafe { &(*pointer).value }
+    }
+}
+impl<T: fmt::Debug> fmt::Debug for Pinned<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.get(), f)
+    }
+}
+
+/// A RAII guard that yields its epoch to the writer periodically, created by
+/// `ReadHandle::read_timeout`.
+#[cfg(feature = "std")]
+pub struct TimedReadHandleGuard<'a, T> {
+    handle: &'a ReadHandle<T>,
+    guard: Option<ReadHandleGuard<'a, T>>,
+    timeout: Duration,
+    deadline: sync::Deadline,
+}
+#[cfg(feature = "std")]
+impl<T> TimedReadHandleGuard<'_, T> {
+    /// Leave the epoch and read the latest value again if the timeout has passed since the guard
+    /// was created or last renewed, returning whether it was renewed. The epoch is only left if
+    /// there are no other guards of the same handle.
+    pub fn renew(&mut self) -> bool {
+        if !sync::has_passed(&self.deadline) {
+            return false;
+        }
+        // The old guard has to be released before reading again, to leave the epoch.
+        self.guard = None;
+        self.guard = Some(self.handle.read());
+        self.deadline = sync::Deadline::now() + self.timeout;
+
+        true
+    }
+}
+#[cfg(feature = "std")]
+impl<T> Deref for TimedReadHandleGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+#[cfg(feature = "std")]
+impl<T: fmt::Debug> fmt::Debug for TimedReadHandleGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
     }
 }
 
 /// A RAII guard used to directly access the data of a read handle, immutably.
 pub struct ReadHandleGuard<'a, T> {
     handle: &'a ReadHandle<T>,
-    epoch: usize,
+    epoch: EpochValue,
     pointer: *const Inner<T>,
 }
 impl<T> Deref for ReadHandleGuard<'_, T> {
@@ -136,10 +579,146 @@ impl<T> Deref for ReadHandleGuard<'_, T> {
         unsafe { &(*self.pointer).value }
     }
 }
+impl<T> AsRef<T> for ReadHandleGuard<'_, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+impl<T> Borrow<T> for ReadHandleGuard<'_, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+impl<T: PartialEq> PartialEq<T> for ReadHandleGuard<'_, T> {
+    fn eq(&self, other: &T) -> bool {
+        **self == *other
+    }
+}
+impl<'g, T> IntoIterator for &'g ReadHandleGuard<'_, T>
+where
+    &'g T: IntoIterator,
+{
+    type Item = <&'g T as IntoIterator>::Item;
+    type IntoIter = <&'g T as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        (**self).into_iter()
+    }
+}
+impl<T: fmt::Debug> fmt::Debug for ReadHandleGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+impl<'a, T> ReadHandleGuard<'a, T> {
+    /// Clone the value this guard is reading.
+    pub fn clone_inner(&self) -> T
+    where
+        T: Clone,
+    {
+        T::clone(self)
+    }
+    /// Get a raw pointer to the value this guard is reading, e.g. to pass it across an FFI
+    /// boundary.
+    ///
+    /// The pointer is valid for as long as this guard is alive, since the writer doesn't mutate
+    /// the buffer until the guard has been dropped, and it must not be used after that. It's not
+    /// stable across guards, since later guards may read the other buffer.
+    pub fn as_ptr(&self) -> *const T {
+        unsafe { &(*self.pointer).value }
+    }
+    /// Get the epoch this guard's handle entered to read, for diagnostics. The epochs of a handle
+    /// count its outermost guards, so they're only meaningful compared to other epochs of the
+    /// same handle, as returned by `ReadHandle::id`.
+    // The cast is only a no-op with the `epoch-u64` feature.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn epoch(&self) -> u64 {
+        (self.epoch & !EPOCH_MSB) as u64
+    }
+    /// Get the version of the data this guard is reading, as in `ReadHandle::version`.
+    pub fn version(&self) -> u64 {
+        unsafe { (*self.pointer).version as u64 }
+    }
+    /// Make a new guard for a component of the inner value, keeping the epoch until the new guard
+    /// has been dropped.
+    pub fn map<U: ?Sized, F: FnOnce(&T) -> &U>(self, f: F) -> MappedReadHandleGuard<'a, U> {
+        let mapped = MappedReadHandleGuard {
+            guards: &self.handle.guards,
+            global_epoch: &self.handle.global_epoch,
+            epoch: self.epoch,
+            pointer: f(&*self),
+        };
+        mem::forget(self);
+        mapped
+    }
+    /// Make a new guard for a component of the inner value like `map`, if the closure returns one.
+    /// Otherwise, the epoch is released right away.
+    pub fn try_map<U: ?Sized, F: FnOnce(&T) -> Option<&U>>(
+        self,
+        f: F,
+    ) -> Option<MappedReadHandleGuard<'a, U>> {
+        let pointer: *const U = f(&*self)?;
+        let mapped = MappedReadHandleGuard {
+            guards: &self.handle.guards,
+            global_epoch: &self.handle.global_epoch,
+            epoch: self.epoch,
+            pointer,
+        };
+        mem::forget(self);
+        Some(mapped)
+    }
+}
 impl<T> Drop for ReadHandleGuard<'_, T> {
     fn drop(&mut self) {
-        self.handle
-            .global_epoch
-            .store(self.epoch | USIZE_MSB, Ordering::Release);
+        release(&self.handle.guards, &self.handle.global_epoch, self.epoch);
+    }
+}
+
+/// A RAII guard for a component of the data of a read handle, created by `ReadHandleGuard::map`.
+pub struct MappedReadHandleGuard<'a, U: ?Sized> {
+    guards: &'a Cell<usize>,
+    global_epoch: &'a AtomicEpoch,
+    epoch: EpochValue,
+    pointer: *const U,
+}
+impl<'a, U: ?Sized> MappedReadHandleGuard<'a, U> {
+    /// Make a new guard for a component of the already mapped value.
+    pub fn map<V: ?Sized, F: FnOnce(&U) -> &V>(self, f: F) -> MappedReadHandleGuard<'a, V> {
+        let mapped = MappedReadHandleGuard {
+            guards: self.guards,
+            global_epoch: self.global_epoch,
+            epoch: self.epoch,
+            pointer: f(&*self),
+        };
+        mem::forget(self);
+        mapped
+    }
+}
+impl<U: ?Sized> Deref for MappedReadHandleGuard<'_, U> {
+    type Target = U;
+    fn deref(&self) -> &U {
+        unsafe { &*self.pointer }
+    }
+}
+impl<U: ?Sized + fmt::Debug> fmt::Debug for MappedReadHandleGuard<'_, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+impl<U: ?Sized> Drop for MappedReadHandleGuard<'_, U> {
+    fn drop(&mut self) {
+        release(self.guards, self.global_epoch, self.epoch);
+    }
+}
+
+/// Drop a guard, and leave the epoch if it was the last one of its handle.
+fn release(guards: &Cell<usize>, global_epoch: &AtomicEpoch, epoch: EpochValue) {
+    let remaining = guards.get() - 1;
+    guards.set(remaining);
+
+    if remaining == 0 {
+        // Makes the reads of the guards happen before a writer that acquires this, and then
+        // mutates the buffer.
+        global_epoch.store(epoch | EPOCH_MSB, Ordering::Release);
     }
 }
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{OperationCache, ReadHandle, ReadHandleGuard, WriteHandle};
+
+impl<T: Serialize> Serialize for ReadHandleGuard<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+impl<T: OperationCache + Serialize> WriteHandle<T> {
+    /// Get a serializable view of the value published by the last refresh.
+    pub fn snapshot_serializable(&self) -> impl Serialize + '_ {
+        self.published()
+    }
+}
+
+/// Create a write handle and a read handle like `new`, to a value deserialized from a
+/// deserializer.
+pub fn from_deserializer<'de, T, D>(
+    deserializer: D,
+) -> Result<(WriteHandle<T>, ReadHandle<T>), D::Error>
+where
+    T: Clone + OperationCache + Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(crate::new)
+}
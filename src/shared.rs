@@ -0,0 +1,63 @@
+use alloc::sync::Arc;
+use core::fmt;
+
+use crate::sync::{self, Mutex};
+use crate::{OperationCache, WriteHandle};
+
+/// A write handle that can be cloned and shared between threads, created by `evc::new_shared`.
+///
+/// Every method locks the writer for as long as it runs, so writers contend with each other,
+/// most notably while one of them refreshes and waits for the readers. Readers are still
+/// lock-free. If there's only one writer at a time, moving a `WriteHandle` between threads avoids
+/// the lock entirely.
+pub struct SharedWriteHandle<T: OperationCache> {
+    inner: Arc<Mutex<WriteHandle<T>>>,
+}
+
+impl<T: OperationCache> SharedWriteHandle<T> {
+    /// Lock the writer, and run a closure with it. The other writers block until it returns.
+    pub fn with_writer<R, F: FnOnce(&mut WriteHandle<T>) -> R>(&self, f: F) -> R {
+        // The writer keeps track of refreshes that panicked by itself.
+        f(&mut sync::lock(&self.inner))
+    }
+    /// Write an operation, see `WriteHandle::write`.
+    pub fn write(&self, operation: T::Operation) {
+        self.with_writer(|w_handle| w_handle.write(operation))
+    }
+    /// Write multiple operations at once, without other writers' operations in between them.
+    pub fn write_many<I: IntoIterator<Item = T::Operation>>(&self, operations: I) {
+        self.with_writer(|w_handle| w_handle.write_many(operations))
+    }
+    /// Refresh the queued writes of all writers, see `WriteHandle::refresh`.
+    pub fn refresh(&self) {
+        self.with_writer(WriteHandle::refresh)
+    }
+    /// Get the number of operations written by all writers that haven't been refreshed yet.
+    pub fn pending_len(&self) -> usize {
+        self.with_writer(|w_handle| w_handle.pending_len())
+    }
+}
+
+impl<T: OperationCache> Clone for SharedWriteHandle<T> {
+    fn clone(&self) -> Self {
+        SharedWriteHandle {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: OperationCache> From<WriteHandle<T>> for SharedWriteHandle<T> {
+    fn from(w_handle: WriteHandle<T>) -> Self {
+        SharedWriteHandle {
+            inner: Arc::new(Mutex::new(w_handle)),
+        }
+    }
+}
+
+impl<T: OperationCache> fmt::Debug for SharedWriteHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedWriteHandle")
+            .field("pending_len", &self.pending_len())
+            .finish()
+    }
+}
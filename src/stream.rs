@@ -0,0 +1,85 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use futures_core::Stream;
+
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::{self, Mutex};
+use crate::InnerPtr;
+
+/// The tasks waiting for the writer to publish a new version.
+#[derive(Default)]
+pub(crate) struct Wakers {
+    wakers: Mutex<Vec<Waker>>,
+    closed: AtomicBool,
+}
+
+impl Wakers {
+    fn register(&self, waker: &Waker) {
+        let mut wakers = sync::lock(&self.wakers);
+
+        if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+    /// Wake all waiting tasks, after a new version has been published.
+    pub(crate) fn wake_all(&self) {
+        for waker in sync::lock(&self.wakers).drain(..) {
+            waker.wake();
+        }
+    }
+    /// Wake all waiting tasks for the last time, after the writer has been dropped.
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.wake_all();
+    }
+}
+
+/// A stream of the versions published by the writer, created by `ReadHandle::version_stream`.
+///
+/// Versions published while the stream isn't being polled are coalesced, so that only the latest
+/// one is yielded. The stream ends when the writer is dropped.
+pub struct VersionStream<T> {
+    inner: Arc<InnerPtr<T>>,
+    last_version: usize,
+}
+
+impl<T> VersionStream<T> {
+    pub(crate) fn new(inner: Arc<InnerPtr<T>>) -> Self {
+        let last_version = inner.version.load(Ordering::Acquire);
+        VersionStream {
+            inner,
+            last_version,
+        }
+    }
+    fn poll_version(&mut self) -> Poll<Option<u64>> {
+        // Check whether the writer is gone first, so that its last version isn't missed.
+        let closed = self.inner.wakers.closed.load(Ordering::Acquire);
+        let version = self.inner.version.load(Ordering::Acquire);
+
+        if version != self.last_version {
+            self.last_version = version;
+            Poll::Ready(Some(version as u64))
+        } else if closed {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> Stream for VersionStream<T> {
+    type Item = u64;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u64>> {
+        if let Poll::Ready(item) = self.poll_version() {
+            return Poll::Ready(item);
+        }
+        self.inner.wakers.register(cx.waker());
+
+        // The writer may have published a version before the waker was registered.
+        self.poll_version()
+    }
+}
@@ -0,0 +1,128 @@
+//! The synchronization primitives that differ between `std` and `no_std` builds, and that are
+//! replaced by `loom`'s when model checking with `--cfg loom`.
+//!
+//! `loom` has no `Weak`, so reference counting always uses `alloc::sync`.
+
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic;
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic;
+
+#[cfg(all(feature = "std", loom))]
+pub(crate) use loom::sync::{Mutex, MutexGuard};
+#[cfg(all(feature = "parking_lot", not(loom)))]
+pub(crate) use parking_lot::{Mutex, MutexGuard};
+#[cfg(all(feature = "std", not(loom), not(feature = "parking_lot")))]
+pub(crate) use std::sync::{Mutex, MutexGuard};
+
+#[cfg(not(feature = "std"))]
+pub(crate) use spin::{Mutex, MutexGuard};
+
+/// The error returned when locking a mutex that was poisoned by a panic while it was locked.
+#[derive(Debug)]
+pub(crate) struct Poisoned;
+
+/// Lock a mutex, failing if it has been poisoned. Only `std` (and `loom`) mutexes can be
+/// poisoned.
+pub(crate) fn try_lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>, Poisoned> {
+    #[cfg(all(feature = "std", any(loom, not(feature = "parking_lot"))))]
+    {
+        mutex.lock().map_err(|_| Poisoned)
+    }
+    #[cfg(not(all(feature = "std", any(loom, not(feature = "parking_lot")))))]
+    {
+        Ok(mutex.lock())
+    }
+}
+
+/// Lock a mutex, even if it has been poisoned.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    #[cfg(all(feature = "std", any(loom, not(feature = "parking_lot"))))]
+    {
+        mutex
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+    #[cfg(not(all(feature = "std", any(loom, not(feature = "parking_lot")))))]
+    {
+        mutex.lock()
+    }
+}
+
+/// Give other threads a chance to run while waiting for them.
+pub(crate) fn yield_now() {
+    #[cfg(loom)]
+    loom::thread::yield_now();
+    #[cfg(all(feature = "std", not(loom)))]
+    std::thread::yield_now();
+    #[cfg(all(not(feature = "std"), not(loom)))]
+    core::hint::spin_loop();
+}
+
+/// Check whether the current thread is unwinding because of a panic.
+#[cfg(feature = "std")]
+pub(crate) fn panicking() -> bool {
+    std::thread::panicking()
+}
+/// Check whether the current thread is unwinding because of a panic, which can't be told without
+/// `std`.
+#[cfg(not(feature = "std"))]
+pub(crate) fn panicking() -> bool {
+    false
+}
+
+/// Busy-wait for a moment. Under `loom`, this lets the other threads run instead, since the
+/// model would never make progress otherwise.
+pub(crate) fn spin_loop() {
+    #[cfg(loom)]
+    loom::hint::spin_loop();
+    #[cfg(not(loom))]
+    core::hint::spin_loop();
+}
+
+/// The point in time after which waiting should be given up. Deadlines require a clock, and thus
+/// can't be created without `std`.
+#[cfg(feature = "std")]
+pub(crate) type Deadline = std::time::Instant;
+
+#[cfg(not(feature = "std"))]
+#[derive(Clone, Copy)]
+pub(crate) enum Deadline {}
+
+/// Check whether the deadline has passed.
+pub(crate) fn has_passed(deadline: &Deadline) -> bool {
+    #[cfg(feature = "std")]
+    {
+        std::time::Instant::now() >= *deadline
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        match *deadline {}
+    }
+}
+
+/// Measures how long something takes. Without `std`, there's no clock, and everything takes no
+/// time.
+pub(crate) struct Stopwatch {
+    #[cfg(feature = "std")]
+    start: std::time::Instant,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Stopwatch {
+            #[cfg(feature = "std")]
+            start: std::time::Instant::now(),
+        }
+    }
+    pub(crate) fn elapsed(&self) -> core::time::Duration {
+        #[cfg(feature = "std")]
+        {
+            self.start.elapsed()
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            core::time::Duration::ZERO
+        }
+    }
+}
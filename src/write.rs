@@ -1,93 +1,801 @@
-use std::mem;
-use std::ptr;
-use std::sync::atomic;
-use std::sync::atomic::{AtomicPtr, Ordering};
-use std::sync::Arc;
-use std::thread;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use core::hash::Hash;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::time::Duration;
 
-use crate::{Epochs, Inner, OperationCache, WeakEpoch, USIZE_MSB};
+use crate::backoff::Backoff;
+use crate::epoch::{EpochValue, EPOCH_MSB};
+use crate::sync::atomic::{self, Ordering};
+use crate::sync::{self, Deadline, Mutex, MutexGuard, Stopwatch};
+use crate::{
+    Epochs, InnerPtr, OperationCache, ReadHandleFactory, Readers, TryOperationCache, WeakEpoch,
+};
+
+/// Statistics about a single refresh, returned by `WriteHandle::refresh_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RefreshStats {
+    /// The number of live readers whose epochs were waited on. Readers are only waited on once a
+    /// previous refresh has seen them read.
+    pub live_readers: usize,
+    /// The number of dropped readers that were removed from the epochs that are waited on.
+    pub dead_readers: usize,
+    /// The number of operations that were applied.
+    pub operations: usize,
+    /// How long the refresh took, including waiting for the readers. This is always zero without
+    /// the `std` feature.
+    pub duration: Duration,
+}
+
+/// How the writer's buffer is brought up to date with the published one, after the readers have
+/// left it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncStrategy {
+    /// Apply the operations of the last refresh again. This is the default.
+    #[default]
+    Replay,
+    /// Clone the published buffer into the writer's, using `Clone::clone_from` to reuse its
+    /// allocations. This can be faster when the operations are expensive compared to cloning.
+    Clone,
+}
+
+/// An error returned by `WriteHandle::try_refresh_checked`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefreshError {
+    /// A panic during an earlier refresh, e.g. in `apply_operation`, may have left the buffers
+    /// inconsistent.
+    Poisoned,
+}
+
+impl fmt::Display for RefreshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefreshError::Poisoned => write!(f, "an earlier refresh panicked"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RefreshError {}
+
+/// An error returned by `WriteHandle::try_into_inner`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntoInnerError {
+    /// There are operations that haven't been refreshed.
+    PendingOps,
+    /// Readers that started before the last refresh are still reading the writer's buffer.
+    Reading,
+}
+
+impl fmt::Display for IntoInnerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntoInnerError::PendingOps => write!(f, "the writer has pending operations"),
+            IntoInnerError::Reading => write!(f, "readers are still reading the writer's buffer"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IntoInnerError {}
+
+/// The progress of waiting for the readers, which can be resumed after unlocking the epochs,
+/// since the active readers are only ever changed by the writer.
+#[derive(Default)]
+struct Wait {
+    start_index: usize,
+}
+
+/// A closure passed to `WriteHandle::absorb`, along with the number of operations written before
+/// it.
+type Absorb<T> = (usize, Box<dyn Fn(&mut T) + Send + Sync>);
+
+/// A closure passed to `WriteHandle::on_refresh`.
+type OnRefresh = Box<dyn FnMut(&RefreshStats) + Send + Sync>;
+/// A closure passed to `WriteHandle::set_wait_hook`.
+type WaitHook = Box<dyn FnMut() + Send + Sync>;
 
 /// A handle which allows accessing the inner data mutably through operations.
 pub struct WriteHandle<T: OperationCache> {
-    writers_inner: Option<Arc<AtomicPtr<Inner<T>>>>,
-    readers_inner: Arc<AtomicPtr<Inner<T>>>,
+    writers_inner: Option<Arc<InnerPtr<T>>>,
+    readers_inner: Arc<InnerPtr<T>>,
 
     epochs: Epochs,
-    last_epochs: Vec<usize>,
+    // The epochs of the readers that have read, and their values when the buffers were last
+    // swapped. Readers that have never read aren't in these, and thus cost nothing to refresh.
+    active: Vec<WeakEpoch>,
+    last_epochs: Vec<EpochValue>,
 
     ops: Vec<T::Operation>,
+    lagging_ops: Vec<T::Operation>,
+    // The sequence numbers of the pending and lagging operations, assigned when they were written,
+    // and the number of the next operation to be written.
+    seqs: Vec<u64>,
+    lagging_seqs: Vec<u64>,
+    next_seq: u64,
+    absorbs: Vec<Absorb<T>>,
+    lagging_absorbs: Vec<Absorb<T>>,
+    // The writes of the open transactions, which aren't pending until the outermost one has been
+    // committed, and the lengths of both at the start of each transaction.
+    transaction_ops: Vec<T::Operation>,
+    transaction_seqs: Vec<u64>,
+    transaction_absorbs: Vec<Absorb<T>>,
+    savepoints: Vec<(usize, usize)>,
+    oplog: Option<Vec<T::Operation>>,
+
+    // Set when syncing by cloning, which can only be chosen when `T: Clone`.
+    clone_from: Option<fn(&mut T, &T)>,
+    // Set when refreshing without readers writes to the readers' buffer only, and set along with
+    // `collapsed` while the writer's buffer is out of date because of that.
+    collapse: Option<fn(&mut T, &T)>,
+    collapsed: Option<fn(&mut T, &T)>,
+    auto_refresh: Option<usize>,
+    on_refresh: Option<OnRefresh>,
+    wait_hook: Option<WaitHook>,
+    flush_on_drop: bool,
+
+    // Set while operations are applied to the writer's buffer, so that it stays set if they panic.
+    poisoned: bool,
 }
 
 impl<T: OperationCache> WriteHandle<T> {
     pub(crate) fn new(
-        writers_inner: Arc<AtomicPtr<Inner<T>>>,
-        readers_inner: Arc<AtomicPtr<Inner<T>>>,
+        writers_inner: Arc<InnerPtr<T>>,
+        readers_inner: Arc<InnerPtr<T>>,
         epochs: Epochs,
+        ops_capacity: usize,
     ) -> Self {
         Self {
             writers_inner: Some(writers_inner),
             readers_inner,
 
             epochs,
+            active: Vec::new(),
             last_epochs: Vec::new(),
-            ops: Vec::new(),
+            ops: Vec::with_capacity(ops_capacity),
+            lagging_ops: Vec::with_capacity(ops_capacity),
+            seqs: Vec::with_capacity(ops_capacity),
+            lagging_seqs: Vec::with_capacity(ops_capacity),
+            next_seq: 0,
+            absorbs: Vec::new(),
+            lagging_absorbs: Vec::new(),
+            transaction_ops: Vec::new(),
+            transaction_seqs: Vec::new(),
+            transaction_absorbs: Vec::new(),
+            savepoints: Vec::new(),
+            oplog: None,
+            clone_from: None,
+            collapse: None,
+            collapsed: None,
+            auto_refresh: None,
+            on_refresh: None,
+            wait_hook: None,
+            flush_on_drop: true,
+
+            poisoned: false,
         }
     }
     /// Mutate the inner data using an operation.
     pub fn write(&mut self, operation: T::Operation) {
-        self.ops.push(operation)
-    }
-    fn wait(&mut self, epochs: &mut Vec<WeakEpoch>) {
-        let mut start_index = 0;
-        let mut retry_count = 0;
-
-        self.last_epochs.resize(epochs.len(), 0);
-
-        'retrying: loop {
-            for index in start_index..self.last_epochs.len() {
-                // Delete the reader from the epochs if the reader has dropped.
-                let epoch = match epochs[index].upgrade() {
-                    Some(e) => e,
-                    None => {
-                        epochs.remove(index);
-                        self.last_epochs.remove(index);
-
-                        // TODO: Maybe this "garbage collecting could happen in another loop?
-                        start_index = 0;
-                        continue 'retrying;
-                    }
-                };
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        let (ops, seqs, _) = self.queue();
+        ops.push(operation);
+        seqs.push(seq);
+        self.refresh_if_needed();
+    }
+    /// Mutate the inner data using multiple operations, in order.
+    pub fn write_many<I: IntoIterator<Item = T::Operation>>(&mut self, operations: I) {
+        let operations = operations.into_iter();
+        let next_seq = self.next_seq;
+
+        let (ops, seqs, _) = self.queue();
+        let len = ops.len();
+        ops.reserve(operations.size_hint().0);
+        ops.extend(operations);
+
+        let written = (ops.len() - len) as u64;
+        seqs.extend(next_seq..next_seq + written);
+        self.next_seq += written;
+        self.refresh_if_needed();
+    }
+    /// Mutate the inner data directly using a closure, for mutations that don't fit an operation.
+    /// The closure is run on both buffers during refreshes, in order with the operations.
+    ///
+    /// Operations aren't compacted while there are pending closures, since compacting could move
+    /// them across a closure.
+    pub fn absorb<F: Fn(&mut T) + Send + Sync + 'static>(&mut self, f: F) {
+        let (ops, _, absorbs) = self.queue();
+        absorbs.push((ops.len(), Box::new(f)));
+        self.refresh_if_needed();
+    }
+    /// Queue writes through the returned guard, and refresh them all when it's dropped.
+    ///
+    /// If the guard is dropped while panicking, it only refreshes when flushing on drop is
+    /// enabled, see `set_flush_on_drop`, and not if the panic came from an earlier refresh.
+    /// Without the `std` feature, panics can't be detected, so the guard always refreshes.
+    pub fn batch(&mut self) -> RefreshGuard<'_, T> {
+        RefreshGuard { w_handle: self }
+    }
+    /// Get where writes go, which is the current transaction if there is one.
+    fn queue(&mut self) -> (&mut Vec<T::Operation>, &mut Vec<u64>, &mut Vec<Absorb<T>>) {
+        if self.savepoints.is_empty() {
+            (&mut self.ops, &mut self.seqs, &mut self.absorbs)
+        } else {
+            (
+                &mut self.transaction_ops,
+                &mut self.transaction_seqs,
+                &mut self.transaction_absorbs,
+            )
+        }
+    }
+    /// Start a transaction, which makes the following writes pending all at once when it's
+    /// committed, or discards them when it's rolled back. Refreshing meanwhile only publishes the
+    /// writes from before the transaction, so every transaction is published as a whole, and many
+    /// of them can be published by a single refresh.
+    ///
+    /// Transactions can be nested, in which case committing the inner one only makes its writes
+    /// part of the outer one. Transactions that are still open when the writer is dropped or
+    /// consumed are rolled back.
+    pub fn begin(&mut self) {
+        self.savepoints
+            .push((self.transaction_ops.len(), self.transaction_absorbs.len()));
+    }
+    /// Commit the innermost open transaction, making its writes pending if no other transaction is
+    /// open.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there's no open transaction.
+    pub fn commit(&mut self) {
+        self.savepoints
+            .pop()
+            .expect("called `commit` without an open transaction");
 
-                if self.last_epochs[index] & USIZE_MSB != 0 {
-                    continue;
+        if self.savepoints.is_empty() {
+            let offset = self.ops.len();
+
+            self.ops.append(&mut self.transaction_ops);
+            self.seqs.append(&mut self.transaction_seqs);
+            self.absorbs.extend(
+                self.transaction_absorbs
+                    .drain(..)
+                    .map(|(position, absorb)| (position + offset, absorb)),
+            );
+            self.refresh_if_needed();
+        }
+    }
+    /// Discard the writes of the innermost open transaction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there's no open transaction.
+    pub fn rollback(&mut self) {
+        let (ops, absorbs) = self
+            .savepoints
+            .pop()
+            .expect("called `rollback` without an open transaction");
+
+        self.transaction_ops.truncate(ops);
+        self.transaction_seqs.truncate(ops);
+        self.transaction_absorbs.truncate(absorbs);
+    }
+    /// Refresh automatically whenever writing makes the number of pending operations reach the
+    /// threshold, including the operation that was just written. `None`, the default, disables
+    /// this.
+    ///
+    /// This makes writing block occasionally, since refreshing has to wait for the readers.
+    pub fn set_auto_refresh(&mut self, threshold: Option<usize>) {
+        self.auto_refresh = threshold;
+    }
+    /// Choose whether dropping the writer refreshes the pending operations first, which is the
+    /// default. Refreshing waits for the readers, and panics if an earlier refresh did, which may
+    /// be unwanted while unwinding.
+    ///
+    /// When disabled, the pending operations are lost when the writer is dropped, and dropping only
+    /// waits for readers that have been reading since before the last refresh.
+    pub fn set_flush_on_drop(&mut self, enabled: bool) {
+        self.flush_on_drop = enabled;
+    }
+    /// Call a closure with the statistics of every refresh that publishes something, e.g. to
+    /// record them in a metrics system. Replaces the previous closure, if any.
+    ///
+    /// The closure runs synchronously on the thread that refreshes, at the end of the refresh, so
+    /// it delays returning from it.
+    pub fn on_refresh<F: FnMut(&RefreshStats) + Send + Sync + 'static>(&mut self, f: F) {
+        self.on_refresh = Some(Box::new(f));
+    }
+    /// Call a closure every time a refresh is waiting for readers, instead of backing off by
+    /// spinning, yielding and eventually sleeping. This can e.g. park the thread, or record how
+    /// long refreshes stall. Replaces the previous closure, if any.
+    ///
+    /// The closure must eventually return, since the readers are only checked again after it does.
+    /// It runs while the epochs are locked, so new readers can't be created meanwhile.
+    pub fn set_wait_hook<F: FnMut() + Send + Sync + 'static>(&mut self, hook: F) {
+        self.wait_hook = Some(Box::new(hook));
+    }
+    fn report(&mut self, stats: &RefreshStats) {
+        if let Some(ref mut on_refresh) = self.on_refresh {
+            on_refresh(stats);
+        }
+    }
+    fn refresh_if_needed(&mut self) {
+        if let Some(threshold) = self.auto_refresh {
+            if self.pending_len() >= threshold {
+                self.refresh();
+            }
+        }
+    }
+    /// Get the number of operations and absorbed closures that have been written but not yet
+    /// refreshed, excluding those of open transactions.
+    pub fn pending_len(&self) -> usize {
+        self.ops.len() + self.absorbs.len()
+    }
+    /// Check whether there are any operations or absorbed closures that have been written but not
+    /// yet refreshed.
+    pub fn has_pending(&self) -> bool {
+        !self.ops.is_empty() || !self.absorbs.is_empty()
+    }
+    /// Iterate over the operations that have been written but not yet refreshed, in order. The
+    /// absorbed closures aren't included.
+    pub fn iter_pending(&self) -> impl Iterator<Item = &T::Operation> + '_ {
+        self.ops.iter()
+    }
+    /// Fold the pending operations into an accumulator, in order, without applying them, e.g. to
+    /// compute the change in size that the next refresh will make.
+    pub fn fold_pending<B, F: FnMut(B, &T::Operation) -> B>(&self, init: B, f: F) -> B {
+        self.ops.iter().fold(init, f)
+    }
+    /// Reserve room for at least `additional` more operations to be queued without reallocating.
+    pub fn reserve(&mut self, additional: usize) {
+        self.ops.reserve(additional);
+        self.seqs.reserve(additional);
+    }
+    /// Shrink the operation buffers as much as possible, to reclaim memory after a large burst of
+    /// writes.
+    pub fn shrink_to_fit(&mut self) {
+        self.ops.shrink_to_fit();
+        self.lagging_ops.shrink_to_fit();
+        self.seqs.shrink_to_fit();
+        self.lagging_seqs.shrink_to_fit();
+        self.absorbs.shrink_to_fit();
+        self.lagging_absorbs.shrink_to_fit();
+        self.transaction_ops.shrink_to_fit();
+        self.transaction_seqs.shrink_to_fit();
+        self.transaction_absorbs.shrink_to_fit();
+    }
+    /// Drop all operations that have been written but not yet refreshed, without applying them to
+    /// either buffer. Readers will keep seeing the state of the last refresh.
+    pub fn discard_pending(&mut self) {
+        self.ops.clear();
+        self.seqs.clear();
+        self.absorbs.clear();
+    }
+    /// Remove and return all operations that have been written but not yet refreshed, without
+    /// applying them to either buffer, e.g. to forward them elsewhere. Like `discard_pending`,
+    /// the pending closures passed to `absorb` are dropped.
+    pub fn drain_pending(&mut self) -> Vec<T::Operation> {
+        self.absorbs.clear();
+        self.seqs.clear();
+        self.ops.drain(..).collect()
+    }
+    /// Keep only the pending operations for which the closure returns `true`, e.g. to drop
+    /// operations that later ones made irrelevant, as a lighter alternative to
+    /// `OperationCache::compact`. The removed operations are never applied to either buffer,
+    /// so the filtering must not change the result that the readers are meant to see.
+    pub fn retain_pending<F: FnMut(&T::Operation) -> bool>(&mut self, mut f: F) {
+        let absorbs = &mut self.absorbs;
+        let seqs = &mut self.seqs;
+        let mut index = 0;
+        let mut absorbed = 0;
+        let mut removed = 0;
+
+        // The closures stay between the same operations as before, and the operations that are
+        // kept keep their sequence numbers.
+        self.ops.retain(|operation| {
+            while let Some((position, _)) = absorbs.get_mut(absorbed) {
+                if *position != index {
+                    break;
                 }
+                *position -= removed;
+                absorbed += 1;
+            }
+            let keep = f(operation);
+            if keep {
+                seqs[index - removed] = seqs[index];
+            } else {
+                removed += 1;
+            }
+            index += 1;
+            keep
+        });
+        seqs.truncate(seqs.len() - removed);
+        for (position, _) in &mut absorbs[absorbed..] {
+            *position -= removed;
+        }
+    }
+    /// Create a factory for read handles to the data of this writer, for when there's no read
+    /// handle at hand to create one from.
+    pub fn reader_factory(&self) -> ReadHandleFactory<T> {
+        ReadHandleFactory::new(Arc::clone(&self.readers_inner), Arc::clone(&self.epochs))
+    }
+    /// Get the number of read handles that haven't been dropped, including the pooled epochs of
+    /// `ReadHandleFactory`s.
+    pub fn reader_count(&self) -> usize {
+        sync::lock(&self.epochs)
+            .epochs
+            .iter()
+            .filter(|epoch| epoch.strong_count() != 0)
+            .count()
+    }
+    /// Get the ids of the readers that the next refresh would have to wait for, since they have
+    /// been reading since before the last refresh, as returned by `ReadHandle::id`. This helps
+    /// finding the reader that makes refreshing hang by never dropping its guard.
+    pub fn stuck_readers(&self) -> Vec<usize> {
+        self.active
+            .iter()
+            .zip(&self.last_epochs)
+            .filter_map(|(epoch, &last_epoch)| epoch.upgrade().map(|epoch| (epoch, last_epoch)))
+            .filter(|(epoch, last_epoch)| {
+                is_still_reading(epoch.load(Ordering::Relaxed), *last_epoch)
+            })
+            .map(|(epoch, _)| crate::epoch_id(&epoch))
+            .collect()
+    }
+    /// Get the strategy used to keep the buffers identical, set by `set_sync_strategy`.
+    pub fn sync_strategy(&self) -> SyncStrategy {
+        match self.clone_from {
+            Some(_) => SyncStrategy::Clone,
+            None => SyncStrategy::Replay,
+        }
+    }
+    /// Remove the readers that have been dropped from the active readers, in a single pass,
+    /// returning how many were removed.
+    fn remove_dead_readers(&mut self) -> usize {
+        debug_assert_eq!(self.last_epochs.len(), self.active.len());
+        let mut live_readers = 0;
 
-                let current_epoch = epoch.load(Ordering::Acquire);
+        for index in 0..self.active.len() {
+            if self.active[index].strong_count() != 0 {
+                self.active.swap(live_readers, index);
+                self.last_epochs.swap(live_readers, index);
+                live_readers += 1;
+            }
+        }
+        let dead_readers = self.active.len() - live_readers;
 
-                if current_epoch == self.last_epochs[index]
-                    && current_epoch & USIZE_MSB == 0
-                    && current_epoch != 0
-                {
-                    start_index = index;
+        self.active.truncate(live_readers);
+        self.last_epochs.truncate(live_readers);
 
-                    if retry_count < 32 {
-                        retry_count += 1;
-                    } else {
-                        thread::yield_now();
-                    }
+        dead_readers
+    }
+    /// Check whether all readers have left the buffer that is about to be mutated, continuing
+    /// from where the previous check of the same wait stopped.
+    fn check_readers(&self, wait: &mut Wait) -> bool {
+        for (index, epoch) in self.active.iter().enumerate().skip(wait.start_index) {
+            if self.last_epochs[index] & EPOCH_MSB != 0 {
+                continue;
+            }
+            // Readers that were dropped during the wait are removed by the next one.
+            let epoch = match epoch.upgrade() {
+                Some(epoch) => epoch,
+                None => continue,
+            };
+            // Acquiring a changed epoch makes the reads of the buffer happen before mutating it.
+            let current_epoch = epoch.load(Ordering::Acquire);
 
-                    continue 'retrying;
+            if is_still_reading(current_epoch, self.last_epochs[index]) {
+                wait.start_index = index;
+                return false;
+            }
+        }
+        true
+    }
+    /// Wait for all readers to leave the buffer that is about to be mutated, returning the number
+    /// of dropped readers that were removed first, or `None` if the deadline passed first.
+    fn wait(&mut self, deadline: Option<Deadline>) -> Option<usize> {
+        let dead_readers = self.remove_dead_readers();
+        let mut wait = Wait::default();
+        let mut backoff = Backoff::new();
+
+        while !self.check_readers(&mut wait) {
+            if let Some(deadline) = deadline {
+                if sync::has_passed(&deadline) {
+                    return None;
                 }
             }
-            break;
+            snooze(&mut self.wait_hook, &mut backoff);
         }
+        Some(dead_readers)
     }
-    /// Refresh the queued writes, making the changes visible to readers.
+    /// Refresh the queued writes, making the changes visible to readers. Does nothing if there are
+    /// no pending operations, unlike `publish`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an earlier refresh panicked, e.g. in `apply_operation`, since the writer's buffer
+    /// may have been left halfway through the operations. The pending operations are kept. Use
+    /// `try_refresh_checked` to get an error instead.
     pub fn refresh(&mut self) {
+        self.refresh_stats();
+    }
+    /// Make the pending operations visible to readers, if there are any. This is the same as
+    /// `refresh`, named after what it does when there's nothing to show.
+    pub fn flush(&mut self) {
+        self.refresh();
+    }
+    /// Publish the writer's buffer like `refresh`, but even if there are no pending operations,
+    /// so that the version always increases. Like `refresh`, this waits for the readers to
+    /// leave the writer's buffer first.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `refresh`.
+    pub fn publish(&mut self) {
+        let stopwatch = Stopwatch::start();
+        let epochs = Arc::clone(&self.epochs);
+        let _readers = self.lock_epochs(&epochs).unwrap();
+
+        self.compact();
+        let dead_readers = self.catch_up(None).unwrap();
+        let live_readers = self.active.len();
+        let operations = self.pending_len();
+        self.publish_pending();
+
+        self.report(&RefreshStats {
+            live_readers,
+            dead_readers,
+            operations,
+            duration: stopwatch.elapsed(),
+        });
+    }
+    /// Refresh the queued writes like `refresh`, returning statistics about the refresh.
+    pub fn refresh_stats(&mut self) -> RefreshStats {
         let epochs = Arc::clone(&self.epochs);
-        let mut epochs = epochs.lock().unwrap();
-        self.wait(&mut epochs);
+        let mut epochs = self.lock_epochs(&epochs).unwrap();
+        self.refresh_until(&mut epochs, None).unwrap()
+    }
+    /// Refresh the queued writes like `refresh`, but give up if the readers haven't left the
+    /// buffer before the timeout. Returns whether the refresh happened; if it didn't, the pending
+    /// operations are kept so that refreshing can be retried later.
+    #[cfg(feature = "std")]
+    pub fn refresh_timeout(&mut self, timeout: Duration) -> bool {
+        let deadline = Deadline::now() + timeout;
+
+        let epochs = Arc::clone(&self.epochs);
+        let mut epochs = self.lock_epochs(&epochs).unwrap();
+        self.refresh_until(&mut epochs, Some(deadline)).is_some()
+    }
+    /// Refresh the queued writes like `refresh`, and then wait until every reader that had read
+    /// before the publish has started reading again, and thus observed the latest version, or has
+    /// been dropped.
+    ///
+    /// This is stronger than `refresh`, which only waits for the readers to leave the buffer that
+    /// is about to be mutated. It never returns if such a reader doesn't read again. Readers can be
+    /// created and dropped meanwhile, and the ones that first read after the publish aren't waited
+    /// for, since they can only observe the latest version.
+    pub fn refresh_and_wait_all(&mut self) {
+        let epochs = Arc::clone(&self.epochs);
+        // The epochs can't stay locked while waiting, since dropping a reader locks them.
+        self.refresh_until(&mut self.lock_epochs(&epochs).unwrap(), None);
+
+        // Readers that first read after the publish aren't active until the next one, and can only
+        // have read its version.
+        let mut backoff = Backoff::new();
+
+        for (epoch, &last_epoch) in self.active.iter().zip(&self.last_epochs) {
+            while let Some(epoch) = epoch.upgrade() {
+                if epoch.load(Ordering::Acquire) & !EPOCH_MSB != last_epoch & !EPOCH_MSB {
+                    break;
+                }
+                snooze(&mut self.wait_hook, &mut backoff);
+            }
+        }
+    }
+    /// Refresh the queued writes like `refresh`, but return an error instead of panicking if the
+    /// epochs mutex has been poisoned.
+    pub fn try_refresh_checked(&mut self) -> Result<(), RefreshError> {
+        let epochs = Arc::clone(&self.epochs);
+        let mut epochs = self.lock_epochs(&epochs)?;
+        self.refresh_until(&mut epochs, None);
+        Ok(())
+    }
+    /// Refresh the queued writes like `refresh`, but yield to the Tokio executor instead of the OS
+    /// scheduler while waiting for readers, and without keeping the epochs locked in the meantime.
+    ///
+    /// This must be run inside a Tokio runtime, and is only beneficial if readers may hold their
+    /// guards across `.await` points, since readers otherwise leave quickly enough for `refresh` to
+    /// be fine.
+    #[cfg(feature = "tokio")]
+    pub async fn refresh_async(&mut self) {
+        self.compact();
+
+        if !self.has_pending() {
+            return;
+        }
+
+        let stopwatch = Stopwatch::start();
+        let epochs = Arc::clone(&self.epochs);
+        let mut wait = Wait::default();
+
+        let dead_readers = self.remove_dead_readers();
+
+        loop {
+            {
+                let _readers = self.lock_epochs(&epochs).unwrap();
+
+                if self.check_readers(&mut wait) {
+                    let live_readers = self.active.len();
+                    let operations = self.pending_len();
+                    self.apply_lagging();
+                    self.publish_pending();
+
+                    self.report(&RefreshStats {
+                        live_readers,
+                        dead_readers,
+                        operations,
+                        duration: stopwatch.elapsed(),
+                    });
+                    return;
+                }
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+    fn refresh_until(
+        &mut self,
+        readers: &mut Readers,
+        deadline: Option<Deadline>,
+    ) -> Option<RefreshStats> {
+        self.compact();
+
+        if !self.has_pending() {
+            return Some(RefreshStats::default());
+        }
+
+        let stopwatch = Stopwatch::start();
+        let mut dead_readers = 0;
+
+        if let Some(clone_from) = self.collapse {
+            dead_readers = self.remove_dead_readers();
+
+            if readers.registered() == 0 {
+                let operations = self.pending_len();
+                self.apply_in_place(clone_from);
+
+                let stats = RefreshStats {
+                    live_readers: 0,
+                    dead_readers,
+                    operations,
+                    duration: stopwatch.elapsed(),
+                };
+                self.report(&stats);
+
+                return Some(stats);
+            }
+        }
+        dead_readers += self.catch_up(deadline)?;
+        // The swap adds the readers that first read since the last one, which it didn't wait on.
+        let live_readers = self.active.len();
+
+        let operations = self.pending_len();
+        self.publish_pending();
+
+        let stats = RefreshStats {
+            live_readers,
+            dead_readers,
+            operations,
+            duration: stopwatch.elapsed(),
+        };
+        self.report(&stats);
+
+        Some(stats)
+    }
+    /// Lock the epochs, failing if an earlier refresh panicked, even when the mutex itself can't be
+    /// poisoned.
+    fn lock_epochs<'a>(
+        &self,
+        epochs: &'a Mutex<Readers>,
+    ) -> Result<MutexGuard<'a, Readers>, RefreshError> {
+        if self.poisoned {
+            return Err(RefreshError::Poisoned);
+        }
+        sync::try_lock(epochs).map_err(|_| RefreshError::Poisoned)
+    }
+    /// Sort the pending operations by their order keys, and compact them, unless there are
+    /// absorbed closures between them.
+    fn compact(&mut self) {
+        order_pending(&mut self.ops, &mut self.seqs, &self.absorbs);
+
+        if self.absorbs.is_empty() {
+            T::compact(&mut self.ops);
+
+            // The operations that are left take the sequence numbers of the last ones, and the
+            // ones that were added get new ones.
+            let len = self.ops.len();
+            if len <= self.seqs.len() {
+                self.seqs.drain(..self.seqs.len() - len);
+            } else {
+                let added = (len - self.seqs.len()) as u64;
+                self.seqs.extend(self.next_seq..self.next_seq + added);
+                self.next_seq += added;
+            }
+        }
+    }
+    /// Wait for the readers to leave the writer's buffer, and apply the operations it's lagging
+    /// behind on. Returns the number of dropped readers, or `None` if the deadline passed first.
+    fn catch_up(&mut self, deadline: Option<Deadline>) -> Option<usize> {
+        let dead_readers = self.wait(deadline)?;
+        self.apply_lagging();
+
+        Some(dead_readers)
+    }
+    /// Apply the operations the writer's buffer is lagging behind on, after the readers have left
+    /// it.
+    fn apply_lagging(&mut self) {
+        let w_handle = &mut unsafe {
+            self.writers_inner
+                .as_ref()
+                .unwrap()
+                .load(Ordering::Relaxed)
+                .as_mut()
+                .unwrap()
+        }
+        .value;
+
+        if let Some(clone_from) = self.collapsed.take() {
+            self.poisoned = true;
+            clone_from(w_handle, self.published());
+            self.poisoned = false;
+        } else if !self.lagging_ops.is_empty() || !self.lagging_absorbs.is_empty() {
+            self.poisoned = true;
+            match self.clone_from {
+                Some(clone_from) => clone_from(w_handle, self.published()),
+                None => apply_pending(
+                    w_handle,
+                    &self.lagging_ops,
+                    &self.lagging_seqs,
+                    &self.lagging_absorbs,
+                ),
+            }
+            self.poisoned = false;
+
+            self.lagging_ops.clear();
+            self.lagging_seqs.clear();
+            self.lagging_absorbs.clear();
+        }
+    }
+    /// Apply the pending operations to the readers' buffer directly, which is only possible while
+    /// there are no readers and the epochs are locked, so that none can be created. The writer's
+    /// buffer is left behind until `apply_lagging` clones the readers' buffer into it.
+    fn apply_in_place(&mut self, clone_from: fn(&mut T, &T)) {
+        let r_handle = unsafe { self.readers_inner.load(Ordering::Relaxed).as_mut().unwrap() };
+
+        self.poisoned = true;
+        apply_pending(&mut r_handle.value, &self.ops, &self.seqs, &self.absorbs);
+        self.poisoned = false;
 
+        // The next readers lock the epochs before reading, which orders this before them.
+        let version = self.readers_inner.version.load(Ordering::Relaxed) + 1;
+        r_handle.version = version;
+        self.readers_inner.version.store(version, Ordering::Release);
+        #[cfg(feature = "futures")]
+        self.readers_inner.wakers.wake_all();
+
+        self.log_published();
+        self.discard_pending();
+        self.lagging_ops.clear();
+        self.lagging_seqs.clear();
+        self.lagging_absorbs.clear();
+        self.collapsed = Some(clone_from);
+    }
+    /// Apply the pending operations to the writer's buffer, and publish it.
+    fn publish_pending(&mut self) {
         let w_handle = &mut unsafe {
             self.writers_inner
                 .as_ref()
@@ -98,24 +806,171 @@ impl<T: OperationCache> WriteHandle<T> {
         }
         .value;
 
-        for operation in self.ops.iter().cloned() {
-            w_handle.apply_operation(operation);
+        self.poisoned = true;
+        apply_pending(w_handle, &self.ops, &self.seqs, &self.absorbs);
+        self.poisoned = false;
+
+        self.swap_buffers();
+    }
+    /// Publish the writer's buffer, after the pending operations have been applied to it.
+    fn swap_buffers(&mut self) {
+        // Only the writer stores the version and the pointers, so loading them is `Relaxed`.
+        let version = self.readers_inner.version.load(Ordering::Relaxed) + 1;
+
+        unsafe {
+            self.writers_inner
+                .as_ref()
+                .unwrap()
+                .load(Ordering::Relaxed)
+                .as_mut()
+                .unwrap()
         }
+        .version = version;
 
-        // Swap the pointers.
+        // Swap the pointers. Releasing the readers' pointer pairs with acquiring it in
+        // `ReadHandle::read`, making the writes to the published buffer visible to the readers,
+        // and the same goes for the version and `ReadHandle::version`.
         let writers_inner = self.writers_inner.as_ref().unwrap().swap(
             self.readers_inner.load(Ordering::Relaxed),
-            Ordering::Release,
+            Ordering::Relaxed,
         );
         self.readers_inner.store(writers_inner, Ordering::Release);
+        self.readers_inner.version.store(version, Ordering::Release);
+        #[cfg(feature = "futures")]
+        self.readers_inner.wakers.wake_all();
 
+        // Pairs with the fence in `ReadHandle::read`: either a reader loads the new pointer, or
+        // the epoch it stored is loaded below, and the next refresh waits for it to change.
         atomic::fence(Ordering::SeqCst);
 
-        for (i, epoch) in epochs.iter().enumerate() {
-            if let Some(e) = epoch.upgrade() {
-                self.last_epochs[i] = e.load(Ordering::Acquire);
-            }
+        // Readers that have read for the first time since the last swap start to be checked.
+        // The others that have never read can only read the buffer that was just published,
+        // since they take the mutex after this, see `ReadHandle::read`.
+        if let Some(ref mut activated) = *sync::lock(&self.readers_inner.activated) {
+            let activated = activated
+                .drain(..)
+                .filter(|epoch| epoch.strong_count() != 0);
+            self.active.extend(activated);
+        }
+        self.last_epochs.resize(self.active.len(), 0);
+
+        for (last_epoch, epoch) in self.last_epochs.iter_mut().zip(&self.active) {
+            // Acquiring the epoch makes the reads of readers that weren't reading happen before
+            // the next refresh.
+            *last_epoch = match epoch.upgrade() {
+                Some(e) => e.load(Ordering::Acquire),
+                None => EPOCH_MSB,
+            };
+        }
+
+        // Readers may still be using the buffer that was just swapped out, so the operations are
+        // kept until the next refresh has waited for them.
+        self.log_published();
+        mem::swap(&mut self.lagging_ops, &mut self.ops);
+        mem::swap(&mut self.lagging_seqs, &mut self.seqs);
+        mem::swap(&mut self.lagging_absorbs, &mut self.absorbs);
+    }
+    /// Append the operations that were just published to the log, if it's enabled.
+    fn log_published(&mut self) {
+        if let Some(ref mut oplog) = self.oplog {
+            oplog.extend(self.ops.iter().cloned());
+        }
+    }
+    /// Start keeping a log of every operation published from now on, in order, e.g. to reconstruct
+    /// the state elsewhere. The log grows until `clear_oplog` is called. Operations are logged
+    /// after `OperationCache::compact`, and absorbed closures aren't logged.
+    pub fn enable_oplog(&mut self) {
+        if self.oplog.is_none() {
+            self.oplog = Some(Vec::new());
+        }
+    }
+    /// Get the operations published since `enable_oplog` was called or the log was last cleared.
+    /// This is empty if the log isn't enabled.
+    pub fn oplog(&self) -> &[T::Operation] {
+        self.oplog.as_deref().unwrap_or(&[])
+    }
+    /// Empty the log of published operations, freeing its memory, and keep logging.
+    pub fn clear_oplog(&mut self) {
+        if let Some(ref mut oplog) = self.oplog {
+            *oplog = Vec::new();
         }
+    }
+    /// Refresh the queued writes like `refresh`, and borrow the value that was published.
+    pub fn refresh_and_read(&mut self) -> &T {
+        self.refresh();
+        self.published()
+    }
+    /// Borrow the value that was published by the last refresh, without any pending operations.
+    pub fn published(&self) -> &T {
+        // The writer is the only one mutating the buffers, and the readers' buffer is never
+        // mutated before being swapped out, which requires `&mut self`. The pointer was stored by
+        // this writer, so loading it is `Relaxed`.
+        unsafe { &(*self.readers_inner.load(Ordering::Relaxed)).value }
+    }
+    /// Bring the writer's buffer up to date like the next refresh would, waiting for the readers to
+    /// leave it first, and panic if it differs from the published one. This catches e.g.
+    /// `OperationCache` impls whose operations don't have the same effect on both buffers.
+    ///
+    /// Only available with debug assertions. Both buffers can only be compared through the
+    /// writer, since readers may be using the published one at any time.
+    #[cfg(debug_assertions)]
+    pub fn assert_buffers_equal(&mut self)
+    where
+        T: PartialEq,
+    {
+        let epochs = Arc::clone(&self.epochs);
+        let readers = self.lock_epochs(&epochs).unwrap();
+        self.catch_up(None);
+        mem::drop(readers);
+
+        // The readers have left the writer's buffer, and only this writer mutates it.
+        let writers =
+            unsafe { &(*self.writers_inner.as_ref().unwrap().load(Ordering::Relaxed)).value };
+        assert!(
+            writers == self.published(),
+            "the writer's buffer differs from the published one"
+        );
+    }
+    /// Consume this writer to retrieve the inner value.
+    pub fn into_inner(mut self) -> T {
+        let epochs = Arc::clone(&self.epochs);
+        let readers = self.lock_epochs(&epochs).unwrap();
+        self.catch_up(None);
+        mem::drop(readers);
+
+        let writers_inner = self.writers_inner.take().unwrap();
+        match Arc::try_unwrap(writers_inner) {
+            Ok(writers_inner) => writers_inner.into_value(),
+            Err(_) => unreachable!("the writer's buffer is only referenced by the writer"),
+        }
+    }
+    /// Consume this writer to retrieve the inner value like `into_inner`, but without refreshing
+    /// or waiting. The writer is returned instead if there are pending operations, or if readers
+    /// haven't left its buffer since the last refresh.
+    // The writer is handed back by value, like `Arc::try_unwrap` does.
+    #[allow(clippy::result_large_err)]
+    pub fn try_into_inner(self) -> Result<T, (Self, IntoInnerError)> {
+        if self.has_pending() {
+            return Err((self, IntoInnerError::PendingOps));
+        }
+        let epochs = Arc::clone(&self.epochs);
+        let readers = self.lock_epochs(&epochs).unwrap();
+        let left = self.check_readers(&mut Wait::default());
+        mem::drop(readers);
+
+        if !left {
+            return Err((self, IntoInnerError::Reading));
+        }
+        Ok(self.into_inner())
+    }
+    /// Consume this writer to retrieve both buffers. The first is the writer's, with the pending
+    /// operations applied to it, and the second is the value published to the readers, which is
+    /// `None` if there are still readers or factories using it.
+    pub fn into_both(mut self) -> (T, Option<T>) {
+        let epochs = Arc::clone(&self.epochs);
+        let readers = self.lock_epochs(&epochs).unwrap();
+        self.catch_up(None);
+        mem::drop(readers);
 
         let w_handle = &mut unsafe {
             self.writers_inner
@@ -127,34 +982,340 @@ impl<T: OperationCache> WriteHandle<T> {
         }
         .value;
 
-        for operation in self.ops.drain(0..self.ops.len()) {
-            w_handle.apply_operation(operation)
+        order_pending(&mut self.ops, &mut self.seqs, &self.absorbs);
+        self.poisoned = true;
+        apply_pending(w_handle, &self.ops, &self.seqs, &self.absorbs);
+        self.poisoned = false;
+        self.discard_pending();
+
+        let readers_inner = Arc::clone(&self.readers_inner);
+        let value = self.into_inner();
+
+        let readers_value = Arc::try_unwrap(readers_inner)
+            .ok()
+            .map(InnerPtr::into_value);
+        (value, readers_value)
+    }
+}
+
+impl<T: TryOperationCache> WriteHandle<T> {
+    /// Refresh the queued writes like `refresh`, but stop at the first operation that fails to
+    /// apply. The operations before it are published, the failed operation is dropped, and the
+    /// operations after it are kept pending.
+    pub fn try_refresh(&mut self) -> Result<(), T::Error> {
+        self.compact();
+
+        if !self.has_pending() {
+            return Ok(());
         }
+
+        let stopwatch = Stopwatch::start();
+        let epochs = Arc::clone(&self.epochs);
+        let _readers = self.lock_epochs(&epochs).unwrap();
+        let dead_readers = self.catch_up(None).unwrap();
+        let live_readers = self.active.len();
+
+        let w_handle = &mut unsafe {
+            self.writers_inner
+                .as_ref()
+                .unwrap()
+                .load(Ordering::Relaxed)
+                .as_mut()
+                .unwrap()
+        }
+        .value;
+
+        let mut result = Ok(());
+        let mut applied = 0;
+        let mut absorbed = 0;
+
+        // The pending operations and closures are only moved once all of them have been tried, so
+        // that they are kept if one of them panics.
+        self.poisoned = true;
+
+        for (operation, &seq) in self.ops.iter().cloned().zip(&self.seqs) {
+            // The closures absorbed before this operation have to run first.
+            while let Some((position, absorb)) = self.absorbs.get(absorbed) {
+                if *position != applied {
+                    break;
+                }
+                absorb(w_handle);
+                absorbed += 1;
+            }
+            if let Err(error) = w_handle.try_apply_operation_seq(operation, seq) {
+                result = Err(error);
+                break;
+            }
+            applied += 1;
+        }
+        if result.is_ok() {
+            for (_, absorb) in &self.absorbs[absorbed..] {
+                absorb(w_handle);
+            }
+            absorbed = self.absorbs.len();
+        }
+
+        self.poisoned = false;
+
+        let mut rest = self.ops.split_off(applied);
+        let mut rest_seqs = self.seqs.split_off(applied);
+        let mut rest_absorbs = self.absorbs.split_off(absorbed);
+
+        if result.is_err() {
+            // The remaining closures come after the failed operation, which is dropped.
+            rest.remove(0);
+            rest_seqs.remove(0);
+            for (position, _) in &mut rest_absorbs {
+                *position -= applied + 1;
+            }
+        }
+
+        let published = applied > 0 || !self.absorbs.is_empty();
+
+        if published {
+            self.swap_buffers();
+        }
+        self.ops.append(&mut rest);
+        self.seqs.append(&mut rest_seqs);
+        self.absorbs = rest_absorbs;
+
+        if published {
+            self.report(&RefreshStats {
+                live_readers,
+                dead_readers,
+                operations: applied + absorbed,
+                duration: stopwatch.elapsed(),
+            });
+        }
+
+        result
     }
-    /// Consume this writer to retrieve the inner value.
-    pub fn into_inner(mut self) -> T {
-        let writers_inner = self.writers_inner.take().unwrap();
-        unsafe { Box::from_raw(writers_inner.swap(ptr::null_mut(), Ordering::Relaxed)) }.value
+}
+
+#[cfg(feature = "std")]
+impl<T: OperationCache> WriteHandle<T>
+where
+    T::Operation: Hash + Eq,
+{
+    /// Remove the pending operations that are equal to an earlier pending one, keeping the first
+    /// of each in order, like `retain_pending`.
+    ///
+    /// This is only correct when applying an operation again has no effect, even after the other
+    /// operations and closures in between, e.g. inserting the same key and value twice.
+    pub fn dedup_pending(&mut self) {
+        let mut seen = std::collections::HashSet::new();
+        self.retain_pending(|operation| seen.insert(operation.clone()));
+    }
+}
+
+impl<T: Clone + OperationCache> WriteHandle<T> {
+    /// Choose how the buffers are kept identical on every refresh. Switching strategies can happen
+    /// at any time.
+    pub fn set_sync_strategy(&mut self, strategy: SyncStrategy) {
+        self.clone_from = match strategy {
+            SyncStrategy::Replay => None,
+            SyncStrategy::Clone => Some(T::clone_from),
+        };
+    }
+    /// Apply the operations of a refresh only to the published buffer when there are no readers,
+    /// instead of waiting for none and applying them to both buffers. Disabled by default.
+    ///
+    /// The buffers are made identical again by cloning the published one into the writer's, on the
+    /// first refresh after a reader has been created, and from then on both buffers are updated as
+    /// usual. This pays off when readers are absent for many refreshes at a time.
+    pub fn set_collapse_without_readers(&mut self, enabled: bool) {
+        self.collapse = if enabled { Some(T::clone_from) } else { None };
+    }
+    /// Replace the whole value on the next refresh, discarding the pending writes, which is cheaper
+    /// than expressing e.g. a full reset as operations. Writes after this are applied on top of the
+    /// new value.
+    ///
+    /// Both buffers are overwritten with clones of the value, in order with the other writes like
+    /// an `absorb`ed closure, which is why the value has to be `Send + Sync + 'static` like those
+    /// closures. Inside a transaction, only the writes of the innermost one are discarded, so that
+    /// rolling it back still keeps the writes before it; the earlier ones are overwritten when
+    /// it's published.
+    pub fn replace(&mut self, value: T)
+    where
+        T: Send + Sync + 'static,
+    {
+        let (ops_start, absorbs_start) = self.savepoints.last().copied().unwrap_or((0, 0));
+        let (ops, seqs, absorbs) = self.queue();
+        ops.truncate(ops_start);
+        seqs.truncate(ops_start);
+        absorbs.truncate(absorbs_start);
+        absorbs.push((
+            ops.len(),
+            Box::new(move |buffer: &mut T| buffer.clone_from(&value)),
+        ));
+        self.refresh_if_needed();
+    }
+    /// Get a copy of the value that was published by the last refresh, without any pending
+    /// operations.
+    pub fn snapshot(&self) -> T {
+        self.published().clone()
+    }
+    /// Get a copy of what the readers will see after the next refresh, i.e. the currently published
+    /// value with the pending operations applied.
+    pub fn peek_pending(&self) -> T {
+        let mut value = self.published().clone();
+        let mut operations = self.ops.clone();
+        let mut seqs = self.seqs.clone();
+        order_pending(&mut operations, &mut seqs, &self.absorbs);
+        apply_pending(&mut value, &operations, &seqs, &self.absorbs);
+        value
+    }
+}
+
+/// A guard that refreshes the writer when dropped, created by `WriteHandle::batch`.
+pub struct RefreshGuard<'a, T: OperationCache> {
+    w_handle: &'a mut WriteHandle<T>,
+}
+
+impl<T: OperationCache> Deref for RefreshGuard<'_, T> {
+    type Target = WriteHandle<T>;
+    fn deref(&self) -> &WriteHandle<T> {
+        self.w_handle
+    }
+}
+impl<T: OperationCache> DerefMut for RefreshGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut WriteHandle<T> {
+        self.w_handle
+    }
+}
+impl<T: OperationCache> fmt::Debug for RefreshGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RefreshGuard")
+            .field("w_handle", &self.w_handle)
+            .finish()
+    }
+}
+impl<T: OperationCache> Drop for RefreshGuard<'_, T> {
+    fn drop(&mut self) {
+        if !sync::panicking() {
+            self.w_handle.refresh();
+        } else if self.w_handle.flush_on_drop {
+            // Panicking again would abort, so a poisoned writer isn't refreshed.
+            let _ = self.w_handle.try_refresh_checked();
+        }
+    }
+}
+
+impl<T: OperationCache> Extend<T::Operation> for WriteHandle<T> {
+    fn extend<I: IntoIterator<Item = T::Operation>>(&mut self, operations: I) {
+        self.write_many(operations)
+    }
+}
+
+impl<T: OperationCache> fmt::Debug for WriteHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let epochs = sync::lock(&self.epochs).epochs.len();
+
+        f.debug_struct("WriteHandle")
+            .field("pending_ops", &self.pending_len())
+            .field("epochs", &epochs)
+            .finish()
     }
 }
 
 impl<T: OperationCache> Drop for WriteHandle<T> {
     fn drop(&mut self) {
         if self.writers_inner.is_some() {
-            if !self.ops.is_empty() {
-                self.refresh();
+            // Publish the pending operations, unless an earlier refresh has panicked.
+            if self.flush_on_drop && self.try_refresh_checked().is_ok() {
+                assert!(!self.has_pending());
             }
-            assert!(self.ops.is_empty());
+        }
+        // Panicking here could abort if the writer is dropped while unwinding, so poisoning is
+        // ignored.
+        let epochs = Arc::clone(&self.epochs);
+        let mut epochs = sync::lock(&epochs);
 
-            let writers_inner = self
-                .writers_inner
-                .as_ref()
-                .unwrap()
-                .swap(ptr::null_mut(), Ordering::Relaxed);
-            mem::drop(unsafe { Box::from_raw(writers_inner) });
+        if self.writers_inner.is_some() {
+            // Readers may still be using the writer's buffer since the last swap.
+            self.wait(None);
         }
+        epochs.compact();
+        epochs.writer_dropped = true;
+        // There are no refreshes left to check the readers that read for the first time.
+        sync::lock(&self.readers_inner.activated).take();
+
+        #[cfg(feature = "futures")]
+        self.readers_inner.wakers.close();
 
-        // The readers should be able to continue reading after this writer has gone, and thus they
-        // should be responsible for destroying their handle.
+        // The writer's buffer is freed along with `writers_inner`. The readers should be able to
+        // continue reading after this writer has gone, and thus the readers' buffer is freed when
+        // the last handle to it is dropped.
+    }
+}
+
+/// Check whether a reader is still reading at the epoch it had when the buffers were last swapped,
+/// and thus may still be reading the buffer that was swapped out. Zero marks readers that have
+/// never read.
+fn is_still_reading(epoch: EpochValue, last_epoch: EpochValue) -> bool {
+    epoch == last_epoch && epoch & EPOCH_MSB == 0 && epoch != 0
+}
+
+/// Wait a little before checking the readers again, with the wait hook if there is one.
+fn snooze(wait_hook: &mut Option<WaitHook>, backoff: &mut Backoff) {
+    match *wait_hook {
+        Some(ref mut hook) => hook(),
+        None => backoff.snooze(),
+    }
+}
+
+/// Stable-sort operations and their sequence numbers by the order keys of the operations, between
+/// the positions of the absorbed closures so that none of them moves across one.
+fn order_pending<T: OperationCache>(
+    operations: &mut Vec<T::Operation>,
+    seqs: &mut Vec<u64>,
+    absorbs: &[Absorb<T>],
+) {
+    let mut start = 0;
+
+    for end in absorbs
+        .iter()
+        .map(|&(position, _)| position)
+        .chain(Some(operations.len()))
+    {
+        // Sorting allocates, which is avoided when the operations are already in order, as they
+        // always are by default.
+        if !operations[start..end]
+            .windows(2)
+            .all(|pair| T::order_key(&pair[0]) <= T::order_key(&pair[1]))
+        {
+            let mut pending = operations
+                .drain(start..end)
+                .zip(seqs.drain(start..end))
+                .collect::<Vec<_>>();
+            pending.sort_by_key(|(operation, _)| T::order_key(operation));
+
+            let (sorted, sorted_seqs): (Vec<_>, Vec<_>) = pending.into_iter().unzip();
+            operations.splice(start..start, sorted);
+            seqs.splice(start..start, sorted_seqs);
+        }
+        start = end;
+    }
+}
+
+/// Apply operations to a buffer along with their sequence numbers, running the absorbed closures
+/// in between them.
+fn apply_pending<T: OperationCache>(
+    value: &mut T,
+    operations: &[T::Operation],
+    seqs: &[u64],
+    absorbs: &[Absorb<T>],
+) {
+    let mut start = 0;
+
+    for (position, absorb) in absorbs {
+        if *position > start {
+            value.apply_operations_seq(&operations[start..*position], &seqs[start..*position]);
+            start = *position;
+        }
+        absorb(value);
+    }
+    if start < operations.len() {
+        value.apply_operations_seq(&operations[start..], &seqs[start..]);
     }
 }
@@ -0,0 +1,87 @@
+//! Tests of `#[derive(OperationCache)]`, run with `cargo test --features derive`.
+#![cfg(feature = "derive")]
+
+extern crate evc;
+
+use evc::OperationCache;
+
+#[derive(Clone, Debug, Default)]
+struct VecWrapper(Vec<u16>);
+
+// The operations of the crate-level example.
+#[derive(Clone, Copy, Debug, OperationCache)]
+#[evc(target = VecWrapper, field = 0)]
+enum Operation {
+    #[evc(push)]
+    Push(u16),
+    #[evc(remove)]
+    Remove(usize),
+    #[evc(clear)]
+    Clear,
+}
+
+#[test]
+fn derive_vec_operations() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Operation::Push(42));
+    w_handle.write(Operation::Push(24));
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[42, 24]);
+
+    w_handle.write(Operation::Push(55));
+    w_handle.write(Operation::Remove(0));
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[24, 55]);
+
+    w_handle.write(Operation::Clear);
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[]);
+}
+
+#[derive(Clone, Debug, Default)]
+struct Counters {
+    counts: Vec<u32>,
+}
+
+impl Counters {
+    fn add(&mut self, index: usize, amount: u32) {
+        if self.counts.len() <= index {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += amount;
+    }
+    fn reset(&mut self) {
+        self.counts.clear();
+    }
+}
+
+// Methods of the type itself, with named fields.
+#[derive(Clone, Debug, OperationCache)]
+#[evc(target = Counters)]
+enum CounterOperation {
+    #[evc(add)]
+    Add { index: usize, amount: u32 },
+    #[evc(reset)]
+    Reset,
+}
+
+#[test]
+fn derive_named_fields() {
+    let (mut w_handle, r_handle) = evc::new(Counters::default());
+
+    w_handle.write(CounterOperation::Add {
+        index: 1,
+        amount: 2,
+    });
+    w_handle.write(CounterOperation::Add {
+        index: 1,
+        amount: 3,
+    });
+    w_handle.refresh();
+    assert_eq!(r_handle.read().counts, &[0, 5]);
+
+    w_handle.write(CounterOperation::Reset);
+    w_handle.refresh();
+    assert!(r_handle.read().counts.is_empty());
+}
@@ -0,0 +1,65 @@
+//! Checks that the epochs of dropped readers are freed, by counting the allocated bytes. This
+//! has its own test binary, so that the allocations of other tests aren't counted.
+#![cfg(not(loom))]
+
+extern crate evc;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use evc::OperationCache;
+
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(pointer, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[derive(Clone, Default)]
+struct Counter(u32);
+
+impl OperationCache for Counter {
+    type Operation = u32;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        self.0 += operation;
+    }
+}
+
+#[test]
+fn free_epochs_after_writer_drop() {
+    let (w_handle, r_handle) = evc::new(Counter::default());
+    mem::drop(w_handle);
+
+    let allocated = ALLOCATED.load(Ordering::SeqCst);
+
+    for _ in 0..10 {
+        let factory = r_handle.factory();
+        let pooled = (0..1000).map(|_| factory.handle()).collect::<Vec<_>>();
+        let clones = (0..1000).map(|_| r_handle.clone()).collect::<Vec<_>>();
+
+        mem::drop(pooled);
+        mem::drop(clones);
+        mem::drop(factory);
+    }
+    // Both the epochs and the slots of the dropped readers have been freed.
+    assert!(
+        ALLOCATED.load(Ordering::SeqCst) <= allocated + 256,
+        "{} bytes are still allocated",
+        ALLOCATED.load(Ordering::SeqCst) - allocated
+    );
+    assert_eq!(r_handle.read().0, 0);
+}
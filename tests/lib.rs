@@ -1,9 +1,13 @@
 extern crate evc;
 
 use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier, Mutex, OnceLock};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use evc::OperationCache;
+use evc::{OperationCache, TryOperationCache};
 
 // A simple struct with only push operations.
 #[derive(Clone, Debug, Default)]
@@ -20,6 +24,15 @@ impl OperationCache for VecWrapper {
     }
 }
 
+impl<'a> IntoIterator for &'a VecWrapper {
+    type Item = &'a u16;
+    type IntoIter = std::slice::Iter<'a, u16>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
 #[test]
 fn basic_sync_operations() {
     let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
@@ -37,6 +50,39 @@ fn basic_sync_operations() {
     assert_eq!(r_handle.read().0, &[57, 94, 42]);
 }
 
+#[test]
+fn default() {
+    let (mut w_handle, r_handle) = evc::default::<VecWrapper>();
+    assert_eq!(r_handle.read().0, &[]);
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1]);
+}
+
+#[test]
+fn new_from_pair() {
+    // An operation cache that can't be cloned.
+    struct Counter(u32);
+
+    impl OperationCache for Counter {
+        type Operation = u32;
+
+        fn apply_operation(&mut self, operation: Self::Operation) {
+            self.0 += operation;
+        }
+    }
+
+    let (mut w_handle, r_handle) = evc::new_from_pair(Counter(1), Counter(1));
+
+    for _ in 0..3 {
+        w_handle.write(2);
+        w_handle.refresh();
+    }
+    assert_eq!(r_handle.read().0, 7);
+    assert_eq!(w_handle.into_inner().0, 7);
+}
+
 #[test]
 fn read_after_drop() {
     let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
@@ -81,6 +127,92 @@ fn multithreaded() {
     }
 }
 
+#[test]
+fn guard_outlives_refresh() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    // The buffer that the guard reads is swapped out, but isn't written to until it's dropped.
+    let guard = r_handle.read();
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    assert_eq!(guard.0, &[1]);
+
+    mem::drop(guard);
+    assert_eq!(r_handle.read().0, &[1, 2]);
+    w_handle.write(Push(3));
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1, 2, 3]);
+}
+
+#[test]
+fn into_inner_waits_for_readers() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let dropped = Arc::new(AtomicBool::new(false));
+
+    let thread = {
+        let dropped = Arc::clone(&dropped);
+        thread::spawn(move || {
+            let guard = r_handle.read();
+            sender.send(()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            assert_eq!(guard.0, &[]);
+
+            dropped.store(true, Ordering::SeqCst);
+            mem::drop(guard);
+        })
+    };
+    receiver.recv().unwrap();
+
+    // The buffer that the guard reads is swapped out, and then returned once it's dropped.
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    assert_eq!(w_handle.into_inner().0, &[1]);
+    assert!(dropped.load(Ordering::SeqCst));
+    thread.join().unwrap();
+}
+
+#[test]
+fn readers_come_and_go() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let r_factory = r_handle.factory();
+    let n = 200;
+
+    // Readers are created and dropped while the writer refreshes, both by pushing new epochs and
+    // by taking the places of dropped readers.
+    let threads = (0..4)
+        .map(|_| {
+            let r_factory = r_factory.clone();
+            thread::spawn(move || loop {
+                let r_handle = r_factory.handle();
+                let guard = r_handle.read();
+                assert!(guard.0.iter().copied().eq(0..guard.0.len() as u16));
+                mem::drop(guard);
+
+                let len = r_factory.read_once(|inner| {
+                    assert!(inner.0.iter().copied().eq(0..inner.0.len() as u16));
+                    inner.0.len()
+                });
+                if len == n {
+                    break;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for index in 0..n as u16 {
+        w_handle.write(Push(index));
+        w_handle.refresh();
+        thread::yield_now();
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(r_handle.read().0.len(), n);
+}
+
 #[test]
 fn write_after_drop() {
     let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
@@ -109,6 +241,21 @@ fn into_factory() {
     assert_eq!(r_handle.read().0, &[100]);
 }
 
+#[test]
+fn from_conversions() {
+    fn read_first<H: Into<evc::ReadHandle<VecWrapper>>>(handle: H) -> Option<u16> {
+        handle.into().read().0.first().copied()
+    }
+
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    let r_factory = evc::ReadHandleFactory::from(r_handle.clone());
+    assert_eq!(read_first(r_factory.clone()), Some(1));
+    assert_eq!(read_first(r_handle), Some(1));
+}
+
 #[test]
 fn into_inner() {
     let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
@@ -124,3 +271,2250 @@ fn into_inner() {
 
     assert_eq!(&r_inner.0, &w_inner.0);
 }
+
+#[test]
+fn into_both() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    w_handle.write(Push(2));
+
+    // The readers' buffer is still in use.
+    let (value, readers_value) = w_handle.into_both();
+    assert_eq!(value.0, &[1, 2]);
+    assert!(readers_value.is_none());
+    assert_eq!(r_handle.read().0, &[1]);
+
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    w_handle.write(Push(2));
+    mem::drop(r_handle);
+
+    let (value, readers_value) = w_handle.into_both();
+    assert_eq!(value.0, &[1, 2]);
+    assert_eq!(readers_value.unwrap().0, &[1]);
+}
+
+#[test]
+fn write_many() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.write_many((2..5).map(Push));
+    w_handle.write(Push(5));
+
+    assert_eq!(r_handle.read().0, &[]);
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1, 2, 3, 4, 5]);
+
+    w_handle.write_many(vec![Push(6), Push(7)]);
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1, 2, 3, 4, 5, 6, 7]);
+}
+
+#[test]
+fn extend() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.extend((0..3).map(Push));
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[0, 1, 2]);
+}
+
+#[test]
+fn pending_len() {
+    let (mut w_handle, _r_handle) = evc::new(VecWrapper::default());
+
+    assert_eq!(w_handle.pending_len(), 0);
+    assert!(!w_handle.has_pending());
+
+    w_handle.write(Push(3));
+    w_handle.write(Push(4));
+
+    assert_eq!(w_handle.pending_len(), 2);
+    assert!(w_handle.has_pending());
+
+    w_handle.refresh();
+
+    assert_eq!(w_handle.pending_len(), 0);
+    assert!(!w_handle.has_pending());
+}
+
+#[test]
+fn discard_pending() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(10));
+    w_handle.refresh();
+
+    w_handle.write(Push(11));
+    w_handle.write(Push(12));
+    w_handle.discard_pending();
+
+    assert!(!w_handle.has_pending());
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[10]);
+
+    w_handle.write(Push(13));
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[10, 13]);
+}
+
+#[test]
+fn drain_pending() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(10));
+    w_handle.refresh();
+
+    w_handle.write_many([Push(11), Push(12)]);
+    let drained = w_handle.drain_pending();
+    assert_eq!(
+        drained.iter().map(|push| push.0).collect::<Vec<_>>(),
+        [11, 12]
+    );
+
+    assert!(!w_handle.has_pending());
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[10]);
+
+    w_handle.write_many(drained);
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[10, 11, 12]);
+}
+
+#[test]
+fn refresh_without_pending() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(8));
+    w_handle.refresh();
+
+    let before = &*r_handle.read() as *const VecWrapper;
+    w_handle.refresh();
+    let after = &*r_handle.read() as *const VecWrapper;
+
+    assert_eq!(before, after);
+    assert_eq!(r_handle.read().0, &[8]);
+}
+
+#[test]
+fn refresh_stats() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let other_r_handle = r_handle.clone();
+
+    assert_eq!(w_handle.refresh_stats(), evc::RefreshStats::default());
+
+    // Readers that have never read aren't waited on.
+    w_handle.write(Push(1));
+    let stats = w_handle.refresh_stats();
+    assert_eq!(stats.live_readers, 0);
+    assert_eq!(stats.operations, 1);
+
+    // Readers that read for the first time are only waited on once a refresh has seen them read.
+    mem::drop((r_handle.read(), other_r_handle.read()));
+    w_handle.write(Push(2));
+    assert_eq!(w_handle.refresh_stats().live_readers, 0);
+
+    w_handle.write(Push(3));
+    let stats = w_handle.refresh_stats();
+    assert_eq!(stats.live_readers, 2);
+    assert_eq!(stats.dead_readers, 0);
+    assert_eq!(stats.operations, 1);
+
+    mem::drop(other_r_handle);
+    w_handle.write(Push(4));
+
+    let stats = w_handle.refresh_stats();
+    assert_eq!(stats.live_readers, 1);
+    assert_eq!(stats.dead_readers, 1);
+    assert_eq!(stats.operations, 1);
+
+    assert_eq!(r_handle.read().0, &[1, 2, 3, 4]);
+}
+
+#[test]
+fn remove_many_dead_readers() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    // Interleave live and dropped readers, so that a scan restarting at every dropped reader would
+    // have to skip all of the live ones before it again. The readers are dropped after all of them
+    // have been created, since new readers take the slots of dropped ones.
+    mem::drop(r_handle.read());
+    let r_handles = (0..40_000).map(|_| r_handle.clone()).collect::<Vec<_>>();
+    for r_handle in &r_handles {
+        mem::drop(r_handle.read());
+    }
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    let r_handles = r_handles.into_iter().step_by(2).collect::<Vec<_>>();
+
+    w_handle.write(Push(3));
+    let start = Instant::now();
+    let stats = w_handle.refresh_stats();
+
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(stats.live_readers, 20_001);
+    assert_eq!(stats.dead_readers, 20_000);
+    assert_eq!(r_handles[0].read().0, &[1, 2, 3]);
+}
+
+#[test]
+fn reuse_dropped_readers() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let r_factory = r_handle.factory();
+
+    for _ in 0..10_000 {
+        let other_r_handle = r_handle.clone();
+        mem::drop(r_handle.clone());
+        mem::drop(other_r_handle);
+        r_factory.read_once(|_| ());
+    }
+
+    // Only the readers alive at the same time were ever registered, and none of them are left.
+    assert_eq!(r_factory.registered_epochs(), 3);
+    assert_eq!(r_factory.live_epochs(), 1);
+
+    // The readers created by `read_once` were vacated as soon as they had read.
+    w_handle.publish();
+    w_handle.write(Push(1));
+    let stats = w_handle.refresh_stats();
+    assert_eq!(stats.live_readers, 0);
+    assert_eq!(stats.dead_readers, 0);
+}
+
+#[test]
+fn stuck_readers() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let other_r_handle = r_handle.clone();
+    assert_ne!(r_handle.id(), other_r_handle.id());
+    mem::drop(other_r_handle.read());
+
+    let guard = r_handle.read();
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    assert_eq!(w_handle.stuck_readers(), &[r_handle.id()]);
+
+    mem::drop(guard);
+    assert!(w_handle.stuck_readers().is_empty());
+}
+
+#[test]
+fn reader_count() {
+    let (w_handle, r_handle) = evc::new(VecWrapper::default());
+    assert_eq!(w_handle.reader_count(), 1);
+
+    let other_r_handle = r_handle.clone();
+    let r_factory = r_handle.factory();
+    assert_eq!(w_handle.reader_count(), 2);
+
+    mem::drop((r_handle, other_r_handle));
+    assert_eq!(w_handle.reader_count(), 0);
+
+    let _r_handle = r_factory.handle();
+    assert_eq!(w_handle.reader_count(), 1);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn refresh_timeout() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    assert!(w_handle.refresh_timeout(Duration::from_millis(10)));
+
+    {
+        let guard = r_handle.read();
+
+        // The guard was taken before the swap, so the first refresh doesn't have to wait.
+        w_handle.write(Push(2));
+        assert!(w_handle.refresh_timeout(Duration::from_millis(10)));
+
+        // But the second one has to wait for the guard to be dropped.
+        w_handle.write(Push(3));
+        assert!(!w_handle.refresh_timeout(Duration::from_millis(10)));
+        assert_eq!(w_handle.pending_len(), 1);
+
+        assert_eq!(guard.0, &[1]);
+    }
+
+    assert!(w_handle.refresh_timeout(Duration::from_millis(10)));
+    assert!(!w_handle.has_pending());
+    assert_eq!(r_handle.read().0, &[1, 2, 3]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn wait_hook() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let waits = Arc::new(AtomicUsize::new(0));
+    {
+        let waits = Arc::clone(&waits);
+        w_handle.set_wait_hook(move || {
+            waits.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(1));
+        });
+    }
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    {
+        let _guard = r_handle.read();
+
+        w_handle.write(Push(2));
+        w_handle.refresh();
+        assert_eq!(waits.load(Ordering::SeqCst), 0);
+
+        // The guard keeps the next refresh waiting, calling the hook until it times out.
+        w_handle.write(Push(3));
+        assert!(!w_handle.refresh_timeout(Duration::from_millis(10)));
+        assert!(waits.load(Ordering::SeqCst) > 0);
+    }
+
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1, 2, 3]);
+}
+
+#[test]
+fn refresh_and_wait_all() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let other_r_handle = r_handle.clone();
+    mem::drop((r_handle.read(), other_r_handle.read()));
+
+    let started = Arc::new(AtomicUsize::new(0));
+    let reader = {
+        let started = Arc::clone(&started);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+
+            // New readers can be created meanwhile, and only see the latest version.
+            let new_r_handle = r_handle.clone();
+            assert_eq!(new_r_handle.read().0, &[1]);
+
+            started.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(r_handle.read().0, &[1]);
+            r_handle
+        })
+    };
+    // The other reader doesn't have to read again, since it is dropped.
+    let dropper = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        mem::drop(other_r_handle);
+    });
+
+    w_handle.write(Push(1));
+    w_handle.refresh_and_wait_all();
+    assert_eq!(started.load(Ordering::SeqCst), 1);
+
+    reader.join().unwrap();
+    dropper.join().unwrap();
+}
+
+#[test]
+fn auto_refresh() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.set_auto_refresh(Some(2));
+
+    w_handle.write(Push(1));
+    assert_eq!(r_handle.read().0, &[]);
+
+    // The operation reaching the threshold is published along with the ones before it.
+    w_handle.write(Push(2));
+    assert_eq!(r_handle.read().0, &[1, 2]);
+    assert!(!w_handle.has_pending());
+
+    w_handle.write_many((3..6).map(Push));
+    assert_eq!(r_handle.read().0, &[1, 2, 3, 4, 5]);
+
+    w_handle.set_auto_refresh(None);
+    w_handle.write_many((6..9).map(Push));
+    assert_eq!(w_handle.pending_len(), 3);
+    assert_eq!(r_handle.read().0, &[1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn publish_and_flush() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.flush();
+    assert_eq!(r_handle.version(), 0);
+    w_handle.publish();
+    assert_eq!(r_handle.version(), 1);
+
+    w_handle.write(Push(1));
+    w_handle.flush();
+    assert_eq!(r_handle.version(), 2);
+    w_handle.publish();
+    assert_eq!(r_handle.version(), 3);
+    assert_eq!(r_handle.read().0, &[1]);
+
+    // The writer's buffer caught up with the operation before being published again.
+    w_handle.write(Push(2));
+    w_handle.publish();
+    assert_eq!(r_handle.read().0, &[1, 2]);
+}
+
+#[test]
+fn builder() {
+    let (mut w_handle, r_handle) = evc::Builder::new()
+        .with_capacity(16)
+        .auto_refresh(2)
+        .sync_strategy(evc::SyncStrategy::Clone)
+        .build(VecWrapper::default());
+    assert_eq!(w_handle.sync_strategy(), evc::SyncStrategy::Clone);
+
+    w_handle.write(Push(1));
+    assert_eq!(r_handle.read().0, &[]);
+    w_handle.write(Push(2));
+    assert_eq!(r_handle.read().0, &[1, 2]);
+
+    let (w_handle, _r_handle) = evc::Builder::default().build(VecWrapper::default());
+    assert_eq!(w_handle.sync_strategy(), evc::SyncStrategy::Replay);
+}
+
+#[test]
+fn shared_write_handle() {
+    let (w_handle, r_handle) = evc::new_shared(VecWrapper::default());
+
+    let writers = (0..4)
+        .map(|i| {
+            let w_handle = w_handle.clone();
+            thread::spawn(move || {
+                w_handle.write_many([Push(i), Push(i)]);
+                w_handle.refresh();
+            })
+        })
+        .collect::<Vec<_>>();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    assert_eq!(w_handle.pending_len(), 0);
+
+    let mut values = r_handle.read().0.clone();
+    // Every batch is written without the other writers' operations in between.
+    assert!(
+        values.chunks(2).all(|pair| pair[0] == pair[1]),
+        "{:?}",
+        values
+    );
+    values.sort_unstable();
+    assert_eq!(values, &[0, 0, 1, 1, 2, 2, 3, 3]);
+
+    w_handle.with_writer(|w_handle| w_handle.write(Push(4)));
+    assert_eq!(w_handle.pending_len(), 1);
+}
+
+#[test]
+fn retain_pending() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write_many([Push(1), Push(2), Push(3)]);
+    w_handle.absorb(|vec| vec.0.push(0));
+    w_handle.write_many([Push(4), Push(5)]);
+
+    w_handle.retain_pending(|push| push.0 % 2 == 1);
+    assert_eq!(w_handle.pending_len(), 4);
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1, 3, 0, 5]);
+
+    w_handle.write(Push(6));
+    w_handle.refresh();
+    assert_eq!(w_handle.into_inner().0, &[1, 3, 0, 5, 6]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn dedup_pending() {
+    #[derive(Clone, Debug, Default)]
+    struct Set(std::collections::BTreeSet<u16>, usize);
+
+    impl OperationCache for Set {
+        type Operation = u16;
+
+        fn apply_operation(&mut self, operation: Self::Operation) {
+            self.0.insert(operation);
+            self.1 += 1;
+        }
+    }
+
+    let (mut w_handle, r_handle) = evc::new(Set::default());
+
+    w_handle.write_many([3, 1, 3, 2, 1, 3]);
+    w_handle.dedup_pending();
+    assert_eq!(
+        w_handle.iter_pending().copied().collect::<Vec<_>>(),
+        &[3, 1, 2]
+    );
+
+    w_handle.refresh();
+    assert_eq!(
+        r_handle.read().0.iter().copied().collect::<Vec<_>>(),
+        &[1, 2, 3]
+    );
+    assert_eq!(r_handle.read().1, 3);
+}
+
+#[test]
+fn try_into_inner() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    let (mut w_handle, error) = w_handle.try_into_inner().unwrap_err();
+    assert_eq!(error, evc::IntoInnerError::PendingOps);
+    assert_eq!(r_handle.read().0, &[]);
+
+    w_handle.refresh();
+    assert_eq!(w_handle.try_into_inner().unwrap().0, &[1]);
+}
+
+#[test]
+fn try_into_inner_with_reader() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    // The guard is still reading the buffer that becomes the writer's again.
+    let guard = r_handle.read();
+    w_handle.write(Push(2));
+    w_handle.refresh();
+
+    let (w_handle, error) = w_handle.try_into_inner().unwrap_err();
+    assert_eq!(error, evc::IntoInnerError::Reading);
+    assert_eq!(guard.0, &[1]);
+
+    mem::drop(guard);
+    assert_eq!(w_handle.try_into_inner().unwrap().0, &[1, 2]);
+}
+
+#[test]
+fn flush_on_drop() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    // Refreshing would make dropping the writer wait for this guard.
+    let guard = r_handle.read();
+    w_handle.write(Push(2));
+    w_handle.set_flush_on_drop(false);
+    mem::drop(w_handle);
+
+    assert_eq!(guard.0, &[1]);
+    mem::drop(guard);
+    assert_eq!(r_handle.read().0, &[1]);
+}
+
+#[test]
+fn batch() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    {
+        let mut batch = w_handle.batch();
+        batch.write(Push(1));
+        batch.write(Push(2));
+        assert_eq!(r_handle.read().0, &[]);
+    }
+    assert_eq!(r_handle.read().0, &[1, 2]);
+    assert_eq!(r_handle.version(), 1);
+}
+
+// Panics can only be detected with `std`.
+#[cfg(feature = "std")]
+#[test]
+fn batch_panic() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write_many([Push(1), Push(2)]);
+    w_handle.refresh();
+
+    // The batch is still refreshed when panicking, since flushing on drop is enabled.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut batch = w_handle.batch();
+        batch.write(Push(3));
+        panic!("oh no");
+    }));
+    assert!(result.is_err());
+    assert_eq!(r_handle.read().0, &[1, 2, 3]);
+
+    w_handle.set_flush_on_drop(false);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut batch = w_handle.batch();
+        batch.write(Push(4));
+        panic!("oh no");
+    }));
+    assert!(result.is_err());
+    assert_eq!(r_handle.read().0, &[1, 2, 3]);
+    assert_eq!(w_handle.pending_len(), 1);
+}
+
+#[test]
+fn iter_pending() {
+    let (mut w_handle, _r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write_many([Push(1), Push(2)]);
+    w_handle.absorb(|vec| vec.0.clear());
+    let pending = w_handle
+        .iter_pending()
+        .map(|push| push.0)
+        .collect::<Vec<_>>();
+    assert_eq!(pending, &[1, 2]);
+
+    w_handle.refresh();
+    assert_eq!(w_handle.iter_pending().count(), 0);
+}
+
+#[test]
+fn fold_pending() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write_many([Push(1), Push(2), Push(3)]);
+    assert_eq!(w_handle.fold_pending(0, |sum, push| sum + push.0), 6);
+    assert_eq!(r_handle.read().0, &[]);
+
+    w_handle.refresh();
+    assert_eq!(w_handle.fold_pending(0, |sum, push| sum + push.0), 0);
+}
+
+#[test]
+fn oplog() {
+    let (mut w_handle, _r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    assert!(w_handle.oplog().is_empty());
+
+    w_handle.enable_oplog();
+    w_handle.write_many([Push(2), Push(3)]);
+    w_handle.refresh();
+    w_handle.write(Push(4));
+    let oplog = w_handle
+        .oplog()
+        .iter()
+        .map(|push| push.0)
+        .collect::<Vec<_>>();
+    assert_eq!(oplog, &[2, 3]);
+
+    // Replaying the log reconstructs the changes since it was enabled.
+    let mut replica = VecWrapper(vec![1]);
+    replica.apply_operations(w_handle.oplog());
+    assert_eq!(replica.0, w_handle.published().0);
+
+    w_handle.clear_oplog();
+    w_handle.refresh();
+    assert_eq!(w_handle.oplog().len(), 1);
+}
+
+#[test]
+fn on_refresh() {
+    let (mut w_handle, _r_handle) = evc::new(VecWrapper::default());
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    w_handle.on_refresh(move |stats| sender.send(*stats).unwrap());
+    w_handle.write_many([Push(1), Push(2)]);
+    let stats = w_handle.refresh_stats();
+    assert_eq!(receiver.try_recv(), Ok(stats));
+    assert_eq!(stats.operations, 2);
+    assert_eq!(stats.live_readers, 0);
+
+    // Refreshing without pending operations doesn't publish anything.
+    w_handle.refresh();
+    assert!(receiver.try_recv().is_err());
+
+    w_handle.publish();
+    assert_eq!(receiver.try_recv().unwrap().operations, 0);
+
+    // Setting another closure replaces the first one.
+    w_handle.on_refresh(|_| {});
+    w_handle.write(Push(3));
+    w_handle.refresh();
+    assert!(receiver.try_recv().is_err());
+}
+
+#[test]
+fn transactions() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.begin();
+    w_handle.write(Push(2));
+    w_handle.absorb(|vec| vec.0.push(3));
+
+    // Only the writes from before the transaction are published.
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1]);
+
+    w_handle.begin();
+    w_handle.write_many([Push(4), Push(5)]);
+    w_handle.rollback();
+    w_handle.begin();
+    w_handle.write(Push(6));
+    w_handle.commit();
+    assert_eq!(w_handle.pending_len(), 0);
+
+    w_handle.write(Push(7));
+    w_handle.commit();
+    assert_eq!(w_handle.pending_len(), 4);
+
+    w_handle.begin();
+    w_handle.write(Push(8));
+    w_handle.rollback();
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1, 2, 3, 6, 7]);
+
+    w_handle.write(Push(9));
+    w_handle.refresh();
+    assert_eq!(w_handle.into_inner().0, &[1, 2, 3, 6, 7, 9]);
+}
+
+#[test]
+#[should_panic(expected = "without an open transaction")]
+fn commit_without_transaction() {
+    let (mut w_handle, _r_handle) = evc::new(VecWrapper::default());
+    w_handle.commit();
+}
+
+#[test]
+fn write_handle_debug() {
+    let (mut w_handle, _r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.write(Push(2));
+
+    let debug = format!("{:?}", w_handle);
+    assert!(debug.contains("pending_ops: 2"), "{}", debug);
+    assert!(debug.contains("epochs: 1"), "{}", debug);
+}
+
+#[test]
+fn read_handle_debug() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(5));
+    w_handle.refresh();
+
+    assert_eq!(format!("{:?}", r_handle.read()), "VecWrapper([5])");
+
+    let debug = format!("{:?}", r_handle);
+    assert!(debug.contains("local_epoch: 1"), "{}", debug);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn nested_reads() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    let outer = r_handle.read();
+    w_handle.write(Push(2));
+    w_handle.refresh();
+
+    {
+        let inner = r_handle.read();
+        assert_eq!(inner.0, &[1, 2]);
+    }
+
+    // Dropping the inner guard must not release the epoch while the outer guard is alive.
+    w_handle.write(Push(3));
+    assert!(!w_handle.refresh_timeout(Duration::from_millis(10)));
+    assert_eq!(outer.0, &[1]);
+
+    mem::drop(outer);
+    assert!(w_handle.refresh_timeout(Duration::from_millis(10)));
+    assert_eq!(r_handle.read().0, &[1, 2, 3]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn map_guard() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.write(Push(2));
+    w_handle.refresh();
+
+    let slice = r_handle.read().map(|wrapper| &wrapper.0[..]);
+    assert_eq!(&*slice, &[1, 2]);
+
+    w_handle.write(Push(3));
+    w_handle.refresh();
+
+    // The mapped guard still holds the epoch, so the writer has to wait for it.
+    w_handle.write(Push(4));
+    assert!(!w_handle.refresh_timeout(Duration::from_millis(10)));
+
+    let first = slice.map(|slice| &slice[0]);
+    assert_eq!(*first, 1);
+    mem::drop(first);
+
+    assert!(w_handle.refresh_timeout(Duration::from_millis(10)));
+    assert_eq!(r_handle.read().0, &[1, 2, 3, 4]);
+}
+
+#[test]
+fn try_map_guard() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    let first = r_handle
+        .read()
+        .try_map(|wrapper| wrapper.0.first())
+        .unwrap();
+    assert_eq!(*first, 1);
+    mem::drop(first);
+
+    // The guard is released when there is nothing to map to.
+    assert!(r_handle
+        .read()
+        .try_map(|wrapper| wrapper.0.get(1))
+        .is_none());
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    w_handle.write(Push(3));
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1, 2, 3]);
+}
+
+#[test]
+fn peek_pending() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    w_handle.write(Push(2));
+    w_handle.write(Push(3));
+
+    assert_eq!(w_handle.peek_pending().0, &[1, 2, 3]);
+    assert_eq!(r_handle.read().0, &[1]);
+    assert_eq!(w_handle.pending_len(), 2);
+
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1, 2, 3]);
+}
+
+#[test]
+fn published() {
+    let (mut w_handle, _r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    w_handle.write(Push(2));
+
+    assert_eq!(w_handle.published().0, &[1]);
+
+    w_handle.refresh();
+    assert_eq!(w_handle.published().0, &[1, 2]);
+}
+
+#[test]
+fn replace() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    w_handle.write(Push(2));
+    w_handle.replace(VecWrapper(vec![7]));
+    w_handle.write(Push(8));
+    assert_eq!(r_handle.read().0, &[1]);
+
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[7, 8]);
+
+    // The writer's buffer has been replaced as well.
+    w_handle.write(Push(9));
+    w_handle.refresh();
+    w_handle.publish();
+    assert_eq!(r_handle.read().0, &[7, 8, 9]);
+}
+
+#[test]
+fn replace_in_transaction() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.begin();
+    w_handle.write_many([Push(1), Push(2), Push(3)]);
+    w_handle.begin();
+    w_handle.replace(VecWrapper(vec![]));
+    w_handle.write_many([Push(4), Push(5)]);
+    // Rolling back the inner transaction keeps the writes of the outer one.
+    w_handle.rollback();
+    w_handle.commit();
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1, 2, 3]);
+
+    w_handle.begin();
+    w_handle.write(Push(6));
+    w_handle.begin();
+    w_handle.replace(VecWrapper(vec![7]));
+    w_handle.write(Push(8));
+    w_handle.commit();
+    w_handle.commit();
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[7, 8]);
+}
+
+#[test]
+fn snapshot() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    w_handle.write(Push(2));
+
+    let snapshot = w_handle.snapshot();
+    assert_eq!(snapshot.0, &[1]);
+    assert_eq!(w_handle.pending_len(), 1);
+    assert_eq!(r_handle.version(), 1);
+
+    w_handle.refresh();
+    assert_eq!(snapshot.0, &[1]);
+    assert_eq!(w_handle.snapshot().0, &[1, 2]);
+}
+
+#[test]
+fn refresh_and_read() {
+    let (mut w_handle, _r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    assert_eq!(w_handle.refresh_and_read().0, &[1]);
+    assert_eq!(w_handle.refresh_and_read().0, &[1]);
+}
+
+#[test]
+fn new_with_capacity() {
+    let (mut w_handle, r_handle) = evc::new_with_capacity(VecWrapper::default(), 64);
+
+    w_handle.write_many((0..64).map(Push));
+    w_handle.refresh();
+
+    assert_eq!(r_handle.read().0.len(), 64);
+}
+
+#[test]
+fn reserve_and_shrink() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.reserve(1024);
+    w_handle.write_many((0..1024).map(Push));
+    w_handle.refresh();
+    w_handle.shrink_to_fit();
+
+    w_handle.write(Push(1024));
+    w_handle.refresh();
+    w_handle.shrink_to_fit();
+
+    assert_eq!(r_handle.read().0.len(), 1025);
+    assert_eq!(w_handle.peek_pending().0.len(), 1025);
+}
+
+// A struct whose operations can fail.
+#[derive(Clone, Debug, Default)]
+struct CheckedVec(Vec<u16>);
+
+#[derive(Clone, Copy, Debug)]
+enum Checked {
+    Push(u16),
+    Remove(usize),
+}
+
+#[derive(Debug, PartialEq)]
+struct OutOfBounds(usize);
+
+impl OperationCache for CheckedVec {
+    type Operation = Checked;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        self.try_apply_operation(operation).unwrap()
+    }
+}
+
+impl TryOperationCache for CheckedVec {
+    type Error = OutOfBounds;
+
+    fn try_apply_operation(&mut self, operation: Self::Operation) -> Result<(), OutOfBounds> {
+        match operation {
+            Checked::Push(value) => self.0.push(value),
+            Checked::Remove(index) if index < self.0.len() => {
+                self.0.remove(index);
+            }
+            Checked::Remove(index) => return Err(OutOfBounds(index)),
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn try_refresh() {
+    let (mut w_handle, r_handle) = evc::new(CheckedVec::default());
+
+    w_handle.write(Checked::Push(1));
+    w_handle.write(Checked::Push(2));
+    w_handle.write(Checked::Remove(5));
+    w_handle.write(Checked::Remove(0));
+
+    assert_eq!(w_handle.try_refresh(), Err(OutOfBounds(5)));
+    assert_eq!(r_handle.read().0, &[1, 2]);
+    assert_eq!(w_handle.pending_len(), 1);
+
+    assert_eq!(w_handle.try_refresh(), Ok(()));
+    assert_eq!(r_handle.read().0, &[2]);
+
+    w_handle.write(Checked::Remove(1));
+    assert_eq!(w_handle.try_refresh(), Err(OutOfBounds(1)));
+    assert!(!w_handle.has_pending());
+
+    assert_eq!(r_handle.read().0, &[2]);
+    assert_eq!(w_handle.into_inner().0, &[2]);
+}
+
+#[test]
+fn absorb() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.absorb(|inner| inner.0.reverse());
+    w_handle.write(Push(2));
+    w_handle.absorb(|inner| inner.0.iter_mut().for_each(|value| *value *= 10));
+    assert_eq!(w_handle.pending_len(), 4);
+
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[10, 20]);
+    assert!(!w_handle.has_pending());
+
+    // The closures are run on the other buffer too.
+    w_handle.absorb(|inner| inner.0.push(3));
+    w_handle.refresh();
+    w_handle.write(Push(4));
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[10, 20, 3, 4]);
+    assert_eq!(w_handle.into_inner().0, &[10, 20, 3, 4]);
+}
+
+#[test]
+fn try_refresh_absorb() {
+    let (mut w_handle, r_handle) = evc::new(CheckedVec::default());
+
+    w_handle.write(Checked::Push(1));
+    w_handle.absorb(|inner| inner.0.push(2));
+    w_handle.write(Checked::Remove(5));
+    w_handle.absorb(|inner| inner.0.push(3));
+    w_handle.write(Checked::Remove(0));
+
+    // The closure before the failed operation is published, and the one after it is kept.
+    assert_eq!(w_handle.try_refresh(), Err(OutOfBounds(5)));
+    assert_eq!(r_handle.read().0, &[1, 2]);
+    assert_eq!(w_handle.pending_len(), 2);
+
+    assert_eq!(w_handle.try_refresh(), Ok(()));
+    assert_eq!(r_handle.read().0, &[2, 3]);
+    assert_eq!(w_handle.into_inner().0, &[2, 3]);
+}
+
+// A struct that pushes all of its operations at once.
+#[derive(Clone, Debug, Default)]
+struct BatchedVec {
+    values: Vec<u16>,
+    batches: usize,
+}
+
+impl OperationCache for BatchedVec {
+    type Operation = Push;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        self.apply_operations_seq(&[operation], &[0])
+    }
+    fn apply_operations_seq(&mut self, operations: &[Self::Operation], _seqs: &[u64]) {
+        self.values.extend(operations.iter().map(|push| push.0));
+        self.batches += 1;
+    }
+}
+
+#[test]
+fn apply_operations() {
+    let (mut w_handle, r_handle) = evc::new(BatchedVec::default());
+
+    w_handle.write_many((0..10).map(Push));
+    w_handle.refresh();
+
+    assert_eq!(r_handle.read().values, (0..10).collect::<Vec<_>>());
+    assert_eq!(r_handle.read().batches, 1);
+
+    w_handle.write_many((10..20).map(Push));
+    w_handle.refresh();
+
+    assert_eq!(r_handle.read().values, (0..20).collect::<Vec<_>>());
+    assert_eq!(r_handle.read().batches, 2);
+}
+
+// A struct that records the sequence number of each operation.
+#[derive(Clone, Debug, Default)]
+struct SequencedVec(Vec<(u16, u64)>);
+
+impl OperationCache for SequencedVec {
+    type Operation = Push;
+
+    fn apply_operation(&mut self, _operation: Self::Operation) {
+        unreachable!("sequenced operations are applied with apply_operation_seq")
+    }
+    fn apply_operation_seq(&mut self, operation: Self::Operation, seq: u64) {
+        self.0.push((operation.0, seq));
+    }
+}
+
+#[test]
+fn operation_seq() {
+    let (mut w_handle, r_handle) = evc::new(SequencedVec::default());
+
+    w_handle.write_many([Push(10), Push(11)]);
+    w_handle.refresh();
+    w_handle.write(Push(12));
+    w_handle.absorb(|vec| vec.0.push((0, 0)));
+    w_handle.write(Push(13));
+    w_handle.refresh();
+    w_handle.write(Push(14));
+    w_handle.discard_pending();
+    w_handle.write(Push(15));
+    w_handle.begin();
+    w_handle.write(Push(16));
+    w_handle.rollback();
+    w_handle.write(Push(17));
+    w_handle.refresh();
+
+    // Discarded and rolled back operations leave gaps.
+    let expected = [(10, 0), (11, 1), (12, 2), (0, 0), (13, 3), (15, 5), (17, 7)];
+    assert_eq!(r_handle.read().0, &expected);
+    // The writer's buffer replays the same sequence numbers.
+    assert_eq!(w_handle.into_inner().0, &expected);
+}
+
+// A struct that counts the operations applied to any of its clones.
+#[derive(Clone, Debug, Default)]
+struct CountingVec {
+    values: Vec<u16>,
+    applied: Arc<AtomicUsize>,
+}
+
+impl OperationCache for CountingVec {
+    type Operation = Push;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        self.values.push(operation.0);
+        self.applied.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn sync_strategy() {
+    let applied = Arc::new(AtomicUsize::new(0));
+    let (mut w_handle, r_handle) = evc::new(CountingVec {
+        values: Vec::new(),
+        applied: Arc::clone(&applied),
+    });
+    assert_eq!(w_handle.sync_strategy(), evc::SyncStrategy::Replay);
+
+    w_handle.set_sync_strategy(evc::SyncStrategy::Clone);
+    assert_eq!(w_handle.sync_strategy(), evc::SyncStrategy::Clone);
+
+    // Every operation is only applied once, to the writer's buffer.
+    for values in [0..3, 3..6, 6..9].iter().cloned() {
+        w_handle.write_many(values.map(Push));
+        w_handle.refresh();
+    }
+    assert_eq!(r_handle.read().values, (0..9).collect::<Vec<_>>());
+    assert_eq!(applied.load(Ordering::SeqCst), 9);
+
+    // Replaying applies the operations of the previous refresh again, including the last one made
+    // while cloning.
+    w_handle.set_sync_strategy(evc::SyncStrategy::Replay);
+    w_handle.write_many((9..12).map(Push));
+    w_handle.refresh();
+    w_handle.write(Push(12));
+    w_handle.refresh();
+
+    assert_eq!(r_handle.read().values, (0..13).collect::<Vec<_>>());
+    assert_eq!(applied.load(Ordering::SeqCst), 9 + (3 + 3) + (3 + 1));
+    assert_eq!(w_handle.published().values, (0..13).collect::<Vec<_>>());
+}
+
+#[test]
+fn collapse_without_readers() {
+    let applied = Arc::new(AtomicUsize::new(0));
+    let (mut w_handle, r_handle) = evc::new(CountingVec {
+        values: Vec::new(),
+        applied: Arc::clone(&applied),
+    });
+    w_handle.set_collapse_without_readers(true);
+    let factory = r_handle.into_factory();
+
+    // Without readers, every operation is only applied once, to the published buffer.
+    for values in [0..3, 3..6].iter().cloned() {
+        w_handle.write_many(values.map(Push));
+        let stats = w_handle.refresh_stats();
+        assert_eq!(stats.live_readers, 0);
+    }
+    assert_eq!(w_handle.published().values, (0..6).collect::<Vec<_>>());
+    assert_eq!(applied.load(Ordering::SeqCst), 6);
+
+    // The writer's buffer catches up by cloning once readers return.
+    let r_handle = factory.handle();
+    assert_eq!(r_handle.read().values, (0..6).collect::<Vec<_>>());
+    w_handle.write_many((6..9).map(Push));
+    w_handle.refresh();
+    assert_eq!(r_handle.read().values, (0..9).collect::<Vec<_>>());
+    assert_eq!(applied.load(Ordering::SeqCst), 9);
+
+    w_handle.write(Push(9));
+    w_handle.refresh();
+    assert_eq!(r_handle.read().values, (0..10).collect::<Vec<_>>());
+    assert_eq!(applied.load(Ordering::SeqCst), 9 + (3 + 1));
+    assert_eq!(w_handle.into_inner().values, (0..10).collect::<Vec<_>>());
+}
+
+// A struct whose pushes are superseded by a later clear.
+#[derive(Clone, Debug, Default)]
+struct ClearableVec {
+    values: Vec<u16>,
+    applied: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Clearable {
+    Push(u16),
+    Clear,
+}
+
+impl OperationCache for ClearableVec {
+    type Operation = Clearable;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        match operation {
+            Clearable::Push(value) => self.values.push(value),
+            Clearable::Clear => self.values.clear(),
+        }
+        self.applied += 1;
+    }
+    fn compact(operations: &mut Vec<Self::Operation>) {
+        if let Some(index) = operations.iter().rposition(|&op| op == Clearable::Clear) {
+            operations.drain(..index);
+        }
+    }
+}
+
+#[test]
+fn compact() {
+    let (mut w_handle, r_handle) = evc::new(ClearableVec::default());
+
+    w_handle.write_many((0..10).map(Clearable::Push));
+    w_handle.write(Clearable::Clear);
+    w_handle.write(Clearable::Push(10));
+
+    let stats = w_handle.refresh_stats();
+    assert_eq!(stats.operations, 2);
+
+    assert_eq!(r_handle.read().values, &[10]);
+    assert_eq!(r_handle.read().applied, 2);
+}
+
+#[test]
+fn order_key() {
+    #[derive(Clone, Debug, Default)]
+    struct List(Vec<u16>);
+
+    #[derive(Clone, Copy, Debug)]
+    enum ListOp {
+        Push(u16),
+        Clear,
+    }
+
+    impl OperationCache for List {
+        type Operation = ListOp;
+
+        fn apply_operation(&mut self, operation: Self::Operation) {
+            match operation {
+                ListOp::Push(value) => self.0.push(value),
+                ListOp::Clear => self.0.clear(),
+            }
+        }
+        fn order_key(operation: &Self::Operation) -> u8 {
+            match operation {
+                ListOp::Clear => 0,
+                ListOp::Push(_) => 1,
+            }
+        }
+    }
+
+    let (mut w_handle, r_handle) = evc::new(List::default());
+    w_handle.write_many([ListOp::Push(1), ListOp::Clear, ListOp::Push(2)]);
+    // Peeking applies the operations in the same order as refreshing.
+    let peeked = w_handle.peek_pending();
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1, 2]);
+    assert_eq!(peeked.0, &[1, 2]);
+
+    // Operations aren't moved across closures.
+    w_handle.write_many([ListOp::Push(3), ListOp::Clear]);
+    w_handle.absorb(|list| list.0.push(9));
+    w_handle.write_many([ListOp::Push(4), ListOp::Clear]);
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[4]);
+
+    // The writer's buffer applies them in the same order.
+    w_handle.publish();
+    assert_eq!(r_handle.read().0, &[4]);
+
+    // And so does consuming the writer.
+    w_handle.write_many([ListOp::Push(5), ListOp::Clear]);
+    mem::drop(r_handle);
+    let (value, _) = w_handle.into_both();
+    assert_eq!(value.0, &[5]);
+}
+
+// A struct counting how many times it has been dropped.
+#[derive(Clone, Debug, Default)]
+struct DropCounter(Arc<AtomicUsize>);
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl OperationCache for DropCounter {
+    type Operation = ();
+
+    fn apply_operation(&mut self, _operation: Self::Operation) {}
+}
+
+#[test]
+fn write_into_inner_frees_readers_buffer() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let (mut w_handle, r_handle) = evc::new(DropCounter(Arc::clone(&drops)));
+
+    // The value passed to `new` is cloned into the readers' buffer, and then moved.
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+
+    w_handle.write(());
+    w_handle.refresh();
+    mem::drop(r_handle);
+
+    let value = w_handle.into_inner();
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+    mem::drop(value);
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn drop_writer_first() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let (mut w_handle, r_handle) = evc::new(DropCounter(Arc::clone(&drops)));
+
+    w_handle.write(());
+    mem::drop(w_handle);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+    mem::drop(r_handle);
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn drop_reader_first() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let (mut w_handle, r_handle) = evc::new(DropCounter(Arc::clone(&drops)));
+
+    w_handle.write(());
+    w_handle.refresh();
+    mem::drop(r_handle);
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+
+    w_handle.write(());
+    mem::drop(w_handle);
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn drop_multiple_readers() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let (mut w_handle, r_handle) = evc::new(DropCounter(Arc::clone(&drops)));
+    let other_r_handle = r_handle.clone();
+    let r_factory = r_handle.factory();
+
+    w_handle.write(());
+    w_handle.refresh();
+
+    mem::drop(r_handle);
+    mem::drop(w_handle);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+    mem::drop(other_r_handle);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+    let last_r_handle = r_factory.into_handle();
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+    assert_eq!(last_r_handle.into_inner().map(|_| ()), Some(()));
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+}
+
+// A struct whose operations panic when pushing `u16::MAX`.
+#[derive(Clone, Debug, Default)]
+struct FragileVec(Vec<u16>);
+
+impl OperationCache for FragileVec {
+    type Operation = u16;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        assert_ne!(operation, u16::MAX, "pushed the sentinel value");
+        self.0.push(operation)
+    }
+}
+
+#[test]
+fn poisoned_refresh() {
+    let (mut w_handle, r_handle) = evc::new(FragileVec::default());
+
+    w_handle.write(1);
+    w_handle.write(u16::MAX);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| w_handle.refresh()));
+    assert!(result.is_err());
+
+    assert_eq!(
+        w_handle.try_refresh_checked(),
+        Err(evc::RefreshError::Poisoned)
+    );
+
+    // The operations are kept, but can't be applied to the buffer that was left halfway through
+    // them.
+    assert_eq!(w_handle.pending_len(), 2);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| w_handle.refresh()));
+    assert!(result.is_err());
+    assert_eq!(w_handle.pending_len(), 2);
+
+    // Readers can still be created and used.
+    let other_r_handle = r_handle.clone();
+    assert_eq!(other_r_handle.read().0, &[]);
+
+    w_handle.discard_pending();
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn read_map() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    assert_eq!(r_handle.read_map(|inner| inner.0.len()), 1);
+
+    // The epoch isn't held after the closure returns, so refreshing twice doesn't have to wait.
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    w_handle.write(Push(3));
+    assert!(w_handle.refresh_timeout(Duration::from_millis(10)));
+    assert_eq!(r_handle.read_map(|inner| inner.0.clone()), &[1, 2, 3]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn pin() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    let pinned = r_handle.pin();
+    assert_eq!(pinned.get().0, &[]);
+
+    // The pinned epoch lets each read see the latest refresh, but blocks the one after it.
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    assert_eq!(pinned.get().0, &[1]);
+    assert!(!r_handle.is_stale());
+
+    w_handle.write(Push(2));
+    assert!(!w_handle.refresh_timeout(Duration::from_millis(10)));
+
+    mem::drop(pinned);
+    assert!(w_handle.refresh_timeout(Duration::from_millis(10)));
+    assert_eq!(r_handle.read().0, &[1, 2]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn read_owned() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    let guard = r_handle.read();
+    assert_eq!(guard.clone_inner().0, &[1]);
+    mem::drop(guard);
+
+    let owned = r_handle.read_owned();
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    w_handle.write(Push(3));
+    assert!(w_handle.refresh_timeout(Duration::from_millis(10)));
+    assert_eq!(owned.0, &[1]);
+}
+
+#[test]
+fn weak_read_handle() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let registry = vec![r_handle.downgrade(), r_handle.downgrade().clone()];
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    for weak in &registry {
+        assert_eq!(weak.upgrade().unwrap().read().0, &[1]);
+    }
+
+    // The writer alone keeps the data alive.
+    mem::drop(r_handle);
+    let r_handle = registry[0].upgrade().unwrap();
+    mem::drop(w_handle);
+    assert_eq!(registry[1].upgrade().unwrap().read().0, &[1]);
+
+    mem::drop(r_handle);
+    assert!(registry.iter().all(|weak| weak.upgrade().is_none()));
+}
+
+#[test]
+fn read_cloned_if_changed() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    let (version, value) = r_handle.read_cloned_if_changed(u64::MAX).unwrap();
+    assert_eq!((version, value.0), (0, vec![]));
+    assert!(r_handle.read_cloned_if_changed(version).is_none());
+
+    // Only the latest of multiple refreshes is returned.
+    for i in 0..3 {
+        w_handle.write(Push(i));
+        w_handle.refresh();
+    }
+    let (version, value) = r_handle.read_cloned_if_changed(version).unwrap();
+    assert_eq!((version, value.0), (3, vec![0, 1, 2]));
+    assert!(r_handle.read_cloned_if_changed(version).is_none());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn reader_factory() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    mem::drop(r_handle);
+
+    let factory = w_handle.reader_factory();
+    let r_handle = factory.handle();
+    assert_eq!(w_handle.reader_count(), 1);
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    assert_eq!(r_handle.read().0, &[1]);
+
+    // The writer waits for the readers of the factory.
+    let guard = r_handle.read();
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    w_handle.write(Push(3));
+    assert!(!w_handle.refresh_timeout(Duration::from_millis(10)));
+    mem::drop(guard);
+    assert!(w_handle.refresh_timeout(Duration::from_millis(10)));
+    assert_eq!(factory.handle().read().0, &[1, 2, 3]);
+}
+
+#[test]
+fn factory_epochs() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let factory = r_handle.factory();
+    let other_r_handle = r_handle.clone();
+    assert_eq!(factory.registered_epochs(), 2);
+    assert_eq!(factory.live_epochs(), 2);
+
+    // The slots of dropped readers stay registered, and are taken by the next new readers.
+    mem::drop(other_r_handle);
+    assert_eq!(factory.registered_epochs(), 2);
+    assert_eq!(factory.live_epochs(), 1);
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    let _other_r_handle = r_handle.clone();
+    assert_eq!(factory.registered_epochs(), 2);
+    assert_eq!(factory.live_epochs(), 2);
+}
+
+#[test]
+fn factory_into_arc() {
+    static FACTORY: OnceLock<Arc<evc::ReadHandleFactory<VecWrapper>>> = OnceLock::new();
+
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    assert!(FACTORY.set(r_handle.factory().into_arc()).is_ok());
+    mem::drop(r_handle);
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    let reader = thread::spawn(|| FACTORY.get().unwrap().handle().read().clone_inner());
+    assert_eq!(reader.join().unwrap().0, &[1]);
+}
+
+#[test]
+fn ptr_eq() {
+    let (_w_handle, r_handle) = evc::new(VecWrapper::default());
+    let (_other_w_handle, other_r_handle) = evc::new(VecWrapper::default());
+
+    assert!(r_handle.ptr_eq(&r_handle.clone()));
+    assert!(r_handle.ptr_eq(&r_handle.factory().handle()));
+    assert!(!r_handle.ptr_eq(&other_r_handle));
+
+    assert!(r_handle.factory().ptr_eq(&r_handle.factory()));
+    assert!(!r_handle.factory().ptr_eq(&other_r_handle.factory()));
+}
+
+#[test]
+fn guard_as_ref_and_borrow() {
+    use std::borrow::Borrow;
+
+    fn len<V: AsRef<VecWrapper>>(value: V) -> usize {
+        value.as_ref().0.len()
+    }
+    fn first<V: Borrow<VecWrapper>>(value: V) -> Option<u16> {
+        value.borrow().0.first().copied()
+    }
+
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(4));
+    w_handle.refresh();
+
+    assert_eq!(len(r_handle.read()), 1);
+    assert_eq!(first(r_handle.read()), Some(4));
+}
+
+#[test]
+fn guard_into_iter() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write_many([Push(1), Push(2), Push(3)]);
+    w_handle.refresh();
+
+    let guard = r_handle.read();
+    let mut sum = 0;
+    for value in &guard {
+        sum += value;
+    }
+    assert_eq!(sum, 6);
+    assert_eq!(guard.0.iter().rev().copied().collect::<Vec<_>>(), [3, 2, 1]);
+}
+
+#[test]
+fn guard_eq() {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Counter(u32);
+
+    impl OperationCache for Counter {
+        type Operation = u32;
+
+        fn apply_operation(&mut self, operation: Self::Operation) {
+            self.0 += operation;
+        }
+    }
+
+    let (mut w_handle, r_handle) = evc::new(Counter(0));
+    w_handle.write(3);
+    w_handle.refresh();
+
+    assert_eq!(r_handle.read(), Counter(3));
+    assert!(r_handle.read() != Counter(0));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn read_timeout() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    let mut guard = r_handle.read_timeout(Duration::from_millis(50));
+    assert!(!guard.renew());
+    assert_eq!(guard.0, &[1]);
+
+    // The guard keeps the writer from refreshing twice until it's renewed.
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    w_handle.write(Push(3));
+    assert!(!w_handle.refresh_timeout(Duration::from_millis(1)));
+    assert_eq!(guard.0, &[1]);
+
+    thread::sleep(Duration::from_millis(50));
+    assert!(guard.renew());
+    assert_eq!(guard.0, &[1, 2]);
+    assert!(w_handle.refresh_timeout(Duration::from_millis(1)));
+    assert!(!guard.renew());
+}
+
+#[test]
+fn guard_as_ptr() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    let guard = r_handle.read();
+    let pointer = guard.as_ptr();
+    assert!(std::ptr::eq(pointer, &*guard));
+
+    // The writer can refresh once, but leaves the buffer of the guard alone.
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    assert_eq!(unsafe { &(*pointer).0 }, &[1]);
+    mem::drop(guard);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn assert_buffers_equal() {
+    #[derive(Clone, Debug, Default, PartialEq)]
+    struct Counter(usize);
+
+    impl OperationCache for Counter {
+        type Operation = ();
+
+        fn apply_operation(&mut self, (): Self::Operation) {
+            self.0 += 1;
+        }
+    }
+
+    let (mut w_handle, _r_handle) = evc::new(Counter::default());
+    w_handle.write(());
+    w_handle.refresh();
+    w_handle.assert_buffers_equal();
+    w_handle.write(());
+    w_handle.refresh();
+    w_handle.assert_buffers_equal();
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "the writer's buffer differs from the published one")]
+fn assert_buffers_differ() {
+    static APPLIED: AtomicUsize = AtomicUsize::new(0);
+
+    // Applies a different value to each buffer.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    struct Nondeterministic(usize);
+
+    impl OperationCache for Nondeterministic {
+        type Operation = ();
+
+        fn apply_operation(&mut self, (): Self::Operation) {
+            self.0 += APPLIED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let (mut w_handle, _r_handle) = evc::new(Nondeterministic::default());
+    w_handle.write(());
+    w_handle.refresh();
+    w_handle.assert_buffers_equal();
+}
+
+#[test]
+fn on_advance() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let advanced = Arc::new(Mutex::new(Vec::new()));
+    {
+        let advanced = Arc::clone(&advanced);
+        r_handle.set_on_advance(move |version| advanced.lock().unwrap().push(version));
+    }
+
+    mem::drop(r_handle.read());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    mem::drop(r_handle.read());
+    mem::drop(r_handle.read());
+    assert_eq!(*advanced.lock().unwrap(), &[1]);
+
+    // Versions published in between aren't observed.
+    w_handle.publish();
+    w_handle.publish();
+    assert_eq!(r_handle.pin().get().0, &[1]);
+    assert_eq!(*advanced.lock().unwrap(), &[1, 3]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn on_advance_panic() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    r_handle.set_on_advance(|_| panic!("oh no"));
+
+    mem::drop(r_handle.read());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| mem::drop(r_handle.read())));
+    assert!(result.is_err());
+
+    // The panic left the epoch, so the writer doesn't wait for the handle.
+    w_handle.write(Push(2));
+    assert!(w_handle.refresh_timeout(Duration::from_millis(50)));
+    w_handle.write(Push(3));
+    assert!(w_handle.refresh_timeout(Duration::from_millis(50)));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn read_unsynchronized() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    // The writer is on this thread, so it can't refresh concurrently.
+    let guard = unsafe { r_handle.read_unsynchronized() };
+    assert_eq!(guard.0, &[1]);
+
+    // The writer still waits for the guard.
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    w_handle.write(Push(3));
+    assert!(!w_handle.refresh_timeout(Duration::from_millis(10)));
+    assert_eq!(guard.0, &[1]);
+    mem::drop(guard);
+
+    assert!(w_handle.refresh_timeout(Duration::from_millis(10)));
+    assert_eq!(unsafe { r_handle.read_unsynchronized() }.0, &[1, 2, 3]);
+}
+
+#[test]
+fn guard_epoch() {
+    let (_w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    let guard = r_handle.read();
+    // Nested guards share the epoch of the outermost one.
+    assert_eq!(r_handle.read().epoch(), guard.epoch());
+    let epoch = guard.epoch();
+    mem::drop(guard);
+
+    assert!(r_handle.read().epoch() > epoch);
+}
+
+#[test]
+fn read_opt() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(7));
+    w_handle.refresh();
+    assert_eq!(r_handle.read_opt().unwrap().0, &[7]);
+
+    assert_eq!(w_handle.into_inner().0, &[7]);
+    assert_eq!(r_handle.read_opt().unwrap().0, &[7]);
+}
+
+#[test]
+fn wait_for_refresh() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    // Nothing has been read yet, so this returns immediately.
+    assert_eq!(r_handle.wait_for_refresh().0, &[1]);
+
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        w_handle.write(Push(2));
+        w_handle.refresh();
+        w_handle
+    });
+
+    assert_eq!(r_handle.wait_for_refresh().0, &[1, 2]);
+
+    let mut w_handle = writer.join().unwrap();
+    w_handle.write(Push(3));
+    w_handle.refresh();
+
+    // The refresh happened before waiting, so this returns immediately too.
+    assert_eq!(r_handle.wait_for_refresh().0, &[1, 2, 3]);
+}
+
+#[test]
+fn is_stale() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    assert!(r_handle.is_stale());
+
+    mem::drop(r_handle.read());
+    assert!(!r_handle.is_stale());
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    assert!(r_handle.is_stale());
+
+    // Refreshing without pending operations doesn't publish a new version.
+    mem::drop(r_handle.read());
+    w_handle.refresh();
+    assert!(!r_handle.is_stale());
+}
+
+#[test]
+fn version() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    assert_eq!(r_handle.version(), 0);
+    assert_eq!(r_handle.read().version(), 0);
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+
+    let guard = r_handle.read();
+    assert_eq!(guard.version(), 1);
+
+    // Refreshing without any pending operations doesn't publish a new version.
+    w_handle.refresh();
+    assert_eq!(r_handle.version(), 1);
+
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    assert_eq!(r_handle.version(), 2);
+    assert_eq!(guard.version(), 1);
+    mem::drop(guard);
+
+    mem::drop(w_handle);
+    assert_eq!(r_handle.version(), 2);
+    assert_eq!(r_handle.read().version(), 2);
+}
+
+#[test]
+fn clone_factory() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let r_factory = r_handle.into_factory();
+
+    w_handle.write(Push(9));
+    w_handle.refresh();
+
+    let threads = (0..4)
+        .map(|_| {
+            let r_factory = r_factory.clone();
+            thread::spawn(move || r_factory.handle().read().0.clone())
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), &[9]);
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn factory_pools_epochs() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let r_factory = r_handle.factory();
+
+    // The epochs of dropped handles are reused, both by the factory and by clones of its handles.
+    mem::drop(r_handle.read());
+    for _ in 0..10 {
+        let pooled = r_factory.handle();
+        mem::drop(pooled.clone().read());
+        mem::drop(pooled.read());
+    }
+    w_handle.publish();
+    w_handle.write(Push(1));
+    assert_eq!(w_handle.refresh_stats().live_readers, 3);
+
+    let pooled = r_factory.handle();
+    let guard = pooled.read();
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    mem::drop(guard);
+    mem::drop(pooled);
+
+    // The reused epoch continues past the guard that was alive during the last refresh.
+    let pooled = r_factory.handle();
+    mem::drop(pooled.read());
+    w_handle.write(Push(3));
+    assert!(w_handle.refresh_timeout(Duration::from_millis(10)));
+    assert_eq!(pooled.read().0, &[1, 2, 3]);
+
+    // Once the factory and its handles are gone, so are the pooled epochs.
+    mem::drop((r_factory, pooled));
+    w_handle.write(Push(4));
+    assert_eq!(w_handle.refresh_stats().live_readers, 1);
+    assert_eq!(r_handle.read().0, &[1, 2, 3, 4]);
+}
+
+#[test]
+fn drop_factory_with_last_handle() {
+    for _ in 0..100 {
+        let applied = Arc::new(AtomicUsize::new(0));
+        let (mut w_handle, r_handle) = evc::new(CountingVec {
+            values: Vec::new(),
+            applied: Arc::clone(&applied),
+        });
+        w_handle.set_collapse_without_readers(true);
+        let r_factory = r_handle.into_factory();
+        let pooled = r_factory.handle();
+
+        let barrier = Arc::new(Barrier::new(2));
+        let thread = {
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                mem::drop(pooled);
+            })
+        };
+        barrier.wait();
+        mem::drop(r_factory);
+        thread.join().unwrap();
+
+        // Whichever is dropped last vacates the pooled epoch, so the writer collapses to a single
+        // buffer, and applies every operation once.
+        w_handle.write(Push(1));
+        w_handle.refresh();
+        w_handle.write(Push(2));
+        w_handle.refresh();
+        assert_eq!(applied.load(Ordering::SeqCst), 2);
+    }
+}
+
+#[test]
+fn read_once() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let r_factory = r_handle.into_factory();
+
+    w_handle.write(Push(4));
+    w_handle.refresh();
+
+    for _ in 0..100 {
+        assert_eq!(r_factory.read_once(|wrapper| wrapper.0.clone()), &[4]);
+    }
+    // Every read took the slot vacated by the previous one.
+    assert_eq!(r_factory.registered_epochs(), 1);
+    assert_eq!(r_factory.live_epochs(), 0);
+
+    let debug = format!("{:?}", w_handle);
+    assert!(debug.contains("epochs: 1"), "{}", debug);
+
+    w_handle.write(Push(5));
+    w_handle.refresh();
+    assert_eq!(r_factory.read_once(|wrapper| wrapper.0.len()), 2);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde() {
+    use serde::de::value::{Error, SeqDeserializer};
+    use serde_test::Token;
+
+    #[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+    #[serde(transparent)]
+    struct Values(Vec<u16>);
+
+    impl OperationCache for Values {
+        type Operation = Push;
+
+        fn apply_operation(&mut self, operation: Self::Operation) {
+            self.0.push(operation.0)
+        }
+    }
+
+    let (mut w_handle, r_handle) = evc::new(Values::default());
+
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    w_handle.write(Push(2));
+
+    let tokens = [Token::Seq { len: Some(1) }, Token::U16(1), Token::SeqEnd];
+    serde_test::assert_ser_tokens(&w_handle.snapshot_serializable(), &tokens);
+    serde_test::assert_ser_tokens(&r_handle.read(), &tokens);
+
+    let deserializer = SeqDeserializer::<_, Error>::new(vec![3u16, 4].into_iter());
+    let (_w_handle, r_handle) = evc::from_deserializer::<Values, _>(deserializer).unwrap();
+    assert_eq!(r_handle.read().0, &[3, 4]);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn refresh_async() {
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+
+    w_handle.write(Push(1));
+    w_handle.refresh_async().await;
+
+    // The reader holds its guard across an await point while the writer waits for it, on the same
+    // thread.
+    let reader = async {
+        let guard = r_handle.read();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(guard.0, &[1]);
+    };
+    let writer = async {
+        tokio::task::yield_now().await;
+
+        w_handle.write(Push(2));
+        w_handle.refresh_async().await;
+        w_handle.write(Push(3));
+        w_handle.refresh_async().await;
+    };
+    tokio::join!(reader, writer);
+
+    assert_eq!(r_handle.read().0, &[1, 2, 3]);
+
+    // Refreshing can also happen on another task.
+    w_handle.write(Push(4));
+    let w_handle = tokio::spawn(async move {
+        w_handle.refresh_async().await;
+        w_handle
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(r_handle.read().0, &[1, 2, 3, 4]);
+    mem::drop(w_handle);
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn version_stream() {
+    use futures::StreamExt;
+
+    let (mut w_handle, r_handle) = evc::new(VecWrapper::default());
+    let mut versions = r_handle.version_stream();
+
+    // Versions published before polling are coalesced into the latest one.
+    w_handle.write(Push(1));
+    w_handle.refresh();
+    w_handle.write(Push(2));
+    w_handle.refresh();
+    let latest = r_handle.version();
+    assert_eq!(futures::executor::block_on(versions.next()), Some(latest));
+
+    let waiter = thread::spawn(move || {
+        let version = futures::executor::block_on(versions.next());
+        let end = futures::executor::block_on(versions.next());
+        (version, end)
+    });
+    thread::sleep(Duration::from_millis(10));
+
+    w_handle.write(Push(3));
+    w_handle.refresh();
+    let latest = r_handle.version();
+    mem::drop(w_handle);
+
+    // The stream ends once the writer is gone.
+    assert_eq!(waiter.join().unwrap(), (Some(latest), None));
+}
+
+#[test]
+fn containers() {
+    use evc::containers::{OperationCacheExt, VecCache};
+
+    let (mut w_handle, r_handle) = evc::new(VecCache::from(vec![1, 2]));
+    w_handle.insert(3);
+    w_handle.remove(0);
+    w_handle.refresh();
+    assert_eq!(r_handle.read().len(), 2);
+    assert_eq!(r_handle.read().as_slice(), &[2, 3]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn hash_containers() {
+    use evc::containers::{HashMapCache, HashSetCache, OperationCacheExt};
+
+    let (mut w_handle, r_handle) = evc::new(HashMapCache::default());
+    w_handle.insert(("a", 1));
+    w_handle.insert(("b", 2));
+    w_handle.remove("a");
+    w_handle.refresh();
+    assert_eq!(r_handle.read().get("b"), Some(&2));
+    assert!(!r_handle.read().contains_key("a"));
+
+    let (mut w_handle, r_handle) = evc::new(HashSetCache::default());
+    w_handle.insert(1);
+    w_handle.insert(1);
+    w_handle.refresh();
+    assert_eq!(r_handle.read().len(), 1);
+
+    w_handle.clear();
+    w_handle.refresh();
+    assert!(r_handle.read().is_empty());
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn map() {
+    let (mut w_handle, r_handle) = evc::map::new();
+
+    w_handle.insert(String::from("a"), 1);
+    w_handle.insert(String::from("b"), 2);
+    w_handle.refresh();
+
+    assert_eq!(r_handle.len(), 2);
+    assert_eq!(r_handle.get("a").as_deref(), Some(&1));
+    assert_eq!(w_handle.get("b"), Some(&2));
+
+    w_handle.remove(String::from("a"));
+    assert!(r_handle.contains_key("a"));
+    w_handle.refresh();
+    assert!(!r_handle.contains_key("a"));
+    assert!(r_handle.get("a").is_none());
+
+    w_handle.clear();
+    w_handle.refresh();
+    assert!(r_handle.is_empty());
+    assert!(w_handle.into_write_handle().into_inner().is_empty());
+}
+
+// A struct that can only be used from one thread.
+#[derive(Clone, Debug, Default)]
+struct LocalVec(std::rc::Rc<Vec<u16>>);
+
+impl OperationCache for LocalVec {
+    type Operation = Push;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        std::rc::Rc::make_mut(&mut self.0).push(operation.0)
+    }
+}
+
+// A struct that can be sent to other threads, but not shared between them.
+#[derive(Clone, Debug, Default)]
+struct CellVec(std::cell::Cell<u16>);
+
+impl OperationCache for CellVec {
+    type Operation = Push;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        self.0.set(operation.0)
+    }
+}
+
+mod auto_traits {
+    use super::{CellVec, LocalVec, VecWrapper};
+    #[cfg(feature = "std")]
+    use evc::TimedReadHandleGuard;
+    use evc::{
+        Pinned, ReadHandle, ReadHandleFactory, ReadHandleGuard, SharedWriteHandle, WeakReadHandle,
+        WriteHandle,
+    };
+    use static_assertions::{assert_impl_all, assert_not_impl_any};
+
+    assert_impl_all!(WriteHandle<VecWrapper>: Send, Sync);
+    assert_impl_all!(SharedWriteHandle<VecWrapper>: Clone, Send, Sync);
+    assert_impl_all!(ReadHandle<VecWrapper>: Send);
+    assert_not_impl_any!(ReadHandle<VecWrapper>: Sync);
+    assert_impl_all!(ReadHandleFactory<VecWrapper>: Send, Sync);
+    assert_not_impl_any!(ReadHandleGuard<'static, VecWrapper>: Send, Sync);
+    assert_not_impl_any!(Pinned<'static, VecWrapper>: Send, Sync);
+    #[cfg(feature = "std")]
+    assert_not_impl_any!(TimedReadHandleGuard<'static, VecWrapper>: Send, Sync);
+    assert_impl_all!(WeakReadHandle<VecWrapper>: Send, Sync);
+
+    // Readers on other threads can access the buffers at the same time as the writer, so all of
+    // the handles need the inner value to be both `Send` and `Sync`.
+    assert_not_impl_any!(WriteHandle<LocalVec>: Send, Sync);
+    assert_not_impl_any!(SharedWriteHandle<LocalVec>: Send, Sync);
+    assert_not_impl_any!(ReadHandle<LocalVec>: Send, Sync);
+    assert_not_impl_any!(ReadHandleFactory<LocalVec>: Send, Sync);
+    assert_not_impl_any!(WeakReadHandle<LocalVec>: Send, Sync);
+
+    assert_not_impl_any!(WriteHandle<CellVec>: Send, Sync);
+    assert_not_impl_any!(ReadHandle<CellVec>: Send, Sync);
+    assert_not_impl_any!(ReadHandleFactory<CellVec>: Send, Sync);
+}
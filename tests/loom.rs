@@ -0,0 +1,116 @@
+//! Model checks of the epoch protocol, run with
+//! `LOOM_MAX_PREEMPTIONS=3 RUSTFLAGS="--cfg loom" cargo test --release --test loom`.
+#![cfg(loom)]
+
+extern crate evc;
+
+use loom::cell::UnsafeCell;
+use loom::thread;
+
+use evc::OperationCache;
+
+// A counter that loom tracks accesses to, so that the writer mutating a buffer that a reader is
+// still reading is reported as a data race.
+struct Counter(UnsafeCell<u32>);
+
+// Only the writer mutates a buffer, and only after the readers have left it.
+unsafe impl Sync for Counter {}
+
+impl Counter {
+    fn new(value: u32) -> Self {
+        Counter(UnsafeCell::new(value))
+    }
+    fn get(&self) -> u32 {
+        self.0.with(|value| unsafe { *value })
+    }
+}
+
+impl Clone for Counter {
+    fn clone(&self) -> Self {
+        Counter::new(self.get())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Add(u32);
+
+impl OperationCache for Counter {
+    type Operation = Add;
+
+    fn apply_operation(&mut self, operation: Self::Operation) {
+        self.0.with_mut(|value| unsafe { *value += operation.0 })
+    }
+}
+
+#[test]
+fn read_while_refreshing() {
+    loom::model(|| {
+        let (mut w_handle, r_handle) = evc::new(Counter::new(0));
+
+        let readers = (0..2)
+            .map(|_| {
+                let r_handle = r_handle.clone();
+
+                thread::spawn(move || {
+                    let first = r_handle.read().get();
+                    let second = r_handle.read().get();
+                    assert!(first <= second && second <= 2);
+                })
+            })
+            .collect::<Vec<_>>();
+
+        w_handle.write(Add(1));
+        w_handle.refresh();
+        w_handle.write(Add(1));
+        w_handle.refresh();
+
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(r_handle.read().get(), 2);
+    });
+}
+
+#[test]
+fn drop_reader_while_refreshing() {
+    loom::model(|| {
+        let (mut w_handle, r_handle) = evc::new(Counter::new(0));
+
+        let reader = thread::spawn(move || {
+            let value = r_handle.read().get();
+            assert!(value <= 2);
+        });
+
+        w_handle.write(Add(1));
+        w_handle.refresh();
+        w_handle.write(Add(1));
+        w_handle.refresh();
+
+        reader.join().unwrap();
+        assert_eq!(w_handle.published().get(), 2);
+    });
+}
+
+#[test]
+fn first_read_while_refreshing() {
+    loom::model(|| {
+        let (mut w_handle, r_handle) = evc::new(Counter::new(0));
+        // A reader that never reads, which the writer skips.
+        let _idle = r_handle.clone();
+
+        let reader = thread::spawn(move || {
+            let guard = r_handle.read();
+            let first = guard.get();
+            thread::yield_now();
+            assert_eq!(guard.get(), first);
+        });
+
+        for _ in 0..3 {
+            w_handle.write(Add(1));
+            w_handle.refresh();
+        }
+
+        reader.join().unwrap();
+        assert_eq!(w_handle.published().get(), 3);
+    });
+}